@@ -1,4 +1,4 @@
-use may_minihttp::{HttpService, HttpServiceFactory, Request, Response};
+use may_minihttp::{ConnectionInfo, HttpService, HttpServiceFactory, Request, Response};
 use std::io;
 
 /// `HelloWorld` is the *service* that we're going to be implementing to service
@@ -19,7 +19,7 @@ struct HelloWorldFac;
 impl HttpServiceFactory for HelloWorldFac {
     type Service = HelloWorld;
 
-    fn new_service(&self, _: usize) -> Self::Service {
+    fn new_service(&self, _: &ConnectionInfo) -> Self::Service {
         HelloWorld
     }
 }
@@ -0,0 +1,30 @@
+use may_minihttp::{HttpServer, Router};
+
+// curl http://127.0.0.1:8080/users/42
+// curl http://127.0.0.1:8080/static/css/site.css
+// curl -X DELETE http://127.0.0.1:8080/users/42
+fn main() {
+    let router = Router::new()
+        .get("/", |_req, rsp, _params| {
+            rsp.body("Hello, world!");
+            Ok(())
+        })
+        .get("/users/:id", |_req, rsp, params| {
+            let id = params.get("id").unwrap_or_default();
+            rsp.header("Content-Type: application/json");
+            rsp.body_vec(format!(r#"{{"id":"{id}"}}"#).into_bytes());
+            Ok(())
+        })
+        .get("/static/*file", |_req, rsp, params| {
+            let file = params.get("file").unwrap_or_default();
+            rsp.body_vec(format!("would serve {file}").into_bytes());
+            Ok(())
+        })
+        .not_found(|_req, rsp, _params| {
+            rsp.body("nothing here");
+            Ok(())
+        });
+
+    let server = HttpServer(router).start("127.0.0.1:8080").unwrap();
+    server.wait();
+}
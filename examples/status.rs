@@ -1,21 +1,21 @@
 use std::io;
 
-use may_minihttp::{HttpServer, HttpService, Request, Response};
+use may_minihttp::{HttpServer, HttpService, Request, Response, StatusCode};
 
 #[derive(Clone)]
 struct StatusService;
 
 impl HttpService for StatusService {
     fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
-        let (code, message) = match req.path() {
-            "/200" => (200, "OK"),
-            "/400" => (400, "Bad Request"),
-            "/500" => (500, "Internal Server Error"),
-            _ => (404, "Not Found"),
+        let status = match req.path() {
+            "/200" => StatusCode::OK,
+            "/400" => StatusCode::BAD_REQUEST,
+            "/500" => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::NOT_FOUND,
         };
 
-        rsp.status_code(code, message);
-        rsp.body(message);
+        rsp.status(status);
+        rsp.body(status.canonical_reason().unwrap_or_default());
         Ok(())
     }
 }
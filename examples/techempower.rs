@@ -24,7 +24,7 @@ mod __impl {
     use std::sync::Arc;
 
     use bytes::BytesMut;
-    use may_minihttp::{HttpService, HttpServiceFactory, Request, Response};
+    use may_minihttp::{ConnectionInfo, HttpService, HttpServiceFactory, Request, Response};
     use may_postgres::{types::ToSql, Client, Statement};
     use nanorand::{Rng, WyRand};
     use smallvec::SmallVec;
@@ -33,12 +33,8 @@ mod __impl {
     mod utils {
         use atoi::FromRadix10;
 
-        pub fn get_query_param(query: &str) -> u16 {
-            let q = if let Some(pos) = query.find("?q") {
-                u16::from_radix_10(query.split_at(pos + 3).1.as_ref()).0
-            } else {
-                1
-            };
+        pub fn get_query_param(q: Option<&str>) -> u16 {
+            let q = q.map_or(1, |q| u16::from_radix_10(q.as_bytes()).0);
             q.clamp(1, 500)
         }
     }
@@ -247,7 +243,7 @@ mod __impl {
     impl HttpService for Techempower {
         fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
             // Bare-bones router
-            match req.path() {
+            match &*req.uri_path() {
                 "/json" => {
                     rsp.header("Content-Type: application/json");
                     let msg = HelloMessage {
@@ -268,15 +264,15 @@ mod __impl {
                     rsp.header("Content-Type: text/html; charset=utf-8");
                     self.db.tell_fortune(rsp.body_mut()).unwrap();
                 }
-                p if p.starts_with("/queries") => {
+                "/queries" => {
                     rsp.header("Content-Type: application/json");
-                    let q = utils::get_query_param(p) as usize;
+                    let q = utils::get_query_param(req.query_param("q").as_deref()) as usize;
                     let worlds = self.db.get_worlds(q, &mut self.rng).unwrap();
                     worlds.to_bytes_mut(rsp.body_mut());
                 }
-                p if p.starts_with("/updates") => {
+                "/updates" => {
                     rsp.header("Content-Type: application/json");
-                    let q = utils::get_query_param(p) as usize;
+                    let q = utils::get_query_param(req.query_param("q").as_deref()) as usize;
                     let worlds = self.db.updates(q, &mut self.rng).unwrap();
                     worlds.to_bytes_mut(rsp.body_mut());
                 }
@@ -296,8 +292,8 @@ mod __impl {
     impl HttpServiceFactory for HttpServer {
         type Service = Techempower;
 
-        fn new_service(&self, id: usize) -> Self::Service {
-            let db = self.db_pool.get_connection(id);
+        fn new_service(&self, conn: &ConnectionInfo) -> Self::Service {
+            let db = self.db_pool.get_connection(conn.id() as usize);
             let rng = WyRand::new();
             Techempower { db, rng }
         }
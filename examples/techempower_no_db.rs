@@ -1,6 +1,6 @@
 use std::io;
 
-use may_minihttp::{HttpService, HttpServiceFactory, Request, Response};
+use may_minihttp::{ConnectionInfo, HttpService, HttpServiceFactory, Request, Response};
 use yarte::Serialize;
 
 #[derive(Serialize)]
@@ -38,7 +38,7 @@ struct HttpServer {}
 impl HttpServiceFactory for HttpServer {
     type Service = Techempower;
 
-    fn new_service(&self, _: usize) -> Self::Service {
+    fn new_service(&self, _: &ConnectionInfo) -> Self::Service {
         Techempower {}
     }
 }
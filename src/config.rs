@@ -1,16 +1,117 @@
+use std::time::Duration;
+
 use crate::request::MaxHeaders;
 
+/// Default capacity of the per-connection request and response buffers
+pub(crate) const DEFAULT_BUF_SIZE: usize = 4096 * 8;
+
+/// Default limit of the request target length
+pub(crate) const DEFAULT_MAX_URI_LEN: usize = 8 * 1024;
+
+/// Default limit of the request head size
+pub(crate) const DEFAULT_MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Default deadline for receiving the PROXY protocol header
+pub(crate) const DEFAULT_PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Configuration for HTTP server behavior
+///
+/// All settings are plain data so a configuration can be built from a config
+/// file at startup and handed to [`ServerBuilder::config`](crate::ServerBuilder::config)
+/// without recompiling the service.
+///
+/// # Examples
+///
+/// ```
+/// use may_minihttp::{HttpConfig, MaxHeaders};
+///
+/// use std::time::Duration;
+///
+/// let config = HttpConfig::new()
+///     .with_max_headers(MaxHeaders::Large)
+///     .with_request_buf_size(64 * 1024)
+///     .with_keep_alive_timeout(Duration::from_secs(75))
+///     .with_header_read_timeout(Duration::from_secs(10));
+/// assert_eq!(config.max_headers.value(), 64);
+/// ```
 #[derive(Debug, Clone, Copy)]
 pub struct HttpConfig {
     /// Maximum number of headers to accept per request
     pub max_headers: MaxHeaders,
+    /// Initial capacity in bytes of the per-connection request buffer
+    pub request_buf_size: usize,
+    /// Initial capacity in bytes of the per-connection response buffer
+    pub response_buf_size: usize,
+    /// Longest request target accepted, longer ones get `414 URI Too Long`
+    pub max_uri_len: usize,
+    /// Largest request head accepted, request line and headers included;
+    /// larger ones get `431 Request Header Fields Too Large`
+    pub max_head_size: usize,
+    /// Largest request body accepted, `None` accepts any size
+    ///
+    /// A larger `Content-Length` fails the first body read before the body
+    /// is received, a chunked body fails the read that crosses the limit.
+    /// The server answers either with `413 Payload Too Large`. Services can
+    /// change it per request with
+    /// [`BodyReader::limit`](crate::BodyReader::limit).
+    pub max_body_size: Option<usize>,
+    /// How long an idle keep-alive connection may wait for the next request
+    /// before it is closed, `None` waits forever
+    pub keep_alive_timeout: Option<Duration>,
+    /// Deadline for receiving a complete request head once its first bytes
+    /// arrived; the connection is closed with `408 Request Timeout`
+    pub header_read_timeout: Option<Duration>,
+    /// Timeout of each read while the service pulls the request body
+    pub body_read_timeout: Option<Duration>,
+    /// Check the response headers before they are written, always on in
+    /// debug builds
+    ///
+    /// A header name that is not a token, a value or reason phrase with CR,
+    /// LF or NUL, or a line without `:` would let a reflected value split the
+    /// response. The service call fails instead and the client gets
+    /// `500 Internal Server Error`.
+    pub strict_headers: bool,
+    /// Answer `500 Internal Server Error` when the service panics
+    ///
+    /// The panic is logged with the request line and the response can be
+    /// customized with [`ErrorHandler::panic`](crate::ErrorHandler::panic).
+    /// The connection stays open unless the request body was left
+    /// unfinished. Has no effect when panics abort, as in the release profile
+    /// of this crate.
+    pub catch_panics: bool,
+    /// Require every connection to start with a PROXY protocol v1 or v2
+    /// header
+    ///
+    /// For servers behind a load balancer such as HAProxy or AWS NLB. The
+    /// header is read before the service of the connection is created, the
+    /// original client it announces is available from
+    /// [`ConnectionInfo`](crate::ConnectionInfo). A connection that does not
+    /// start with a valid header within
+    /// [`proxy_header_timeout`](Self::proxy_header_timeout) is closed without
+    /// a response. Only turn it on if all the connections come from the load
+    /// balancer, anyone else could announce any address.
+    pub proxy_protocol: bool,
+    /// Deadline for receiving the PROXY protocol header once the connection
+    /// is accepted
+    pub proxy_header_timeout: Duration,
 }
 
 impl Default for HttpConfig {
     fn default() -> Self {
         Self {
             max_headers: MaxHeaders::Default,
+            request_buf_size: DEFAULT_BUF_SIZE,
+            response_buf_size: DEFAULT_BUF_SIZE,
+            max_uri_len: DEFAULT_MAX_URI_LEN,
+            max_head_size: DEFAULT_MAX_HEAD_SIZE,
+            max_body_size: None,
+            keep_alive_timeout: None,
+            header_read_timeout: None,
+            body_read_timeout: None,
+            strict_headers: false,
+            catch_panics: false,
+            proxy_protocol: false,
+            proxy_header_timeout: DEFAULT_PROXY_HEADER_TIMEOUT,
         }
     }
 }
@@ -20,11 +121,82 @@ impl HttpConfig {
     pub fn new() -> Self {
         Self::default()
     }
-    
+
     /// Set the maximum number of headers
     pub fn with_max_headers(mut self, max_headers: MaxHeaders) -> Self {
         self.max_headers = max_headers;
         self
     }
-}
 
+    /// Set the initial capacity of the per-connection request buffer
+    pub fn with_request_buf_size(mut self, size: usize) -> Self {
+        self.request_buf_size = size;
+        self
+    }
+
+    /// Set the initial capacity of the per-connection response buffer
+    pub fn with_response_buf_size(mut self, size: usize) -> Self {
+        self.response_buf_size = size;
+        self
+    }
+
+    /// Set the longest accepted request target
+    pub fn with_max_uri_len(mut self, len: usize) -> Self {
+        self.max_uri_len = len;
+        self
+    }
+
+    /// Set the largest accepted request head
+    pub fn with_max_head_size(mut self, size: usize) -> Self {
+        self.max_head_size = size;
+        self
+    }
+
+    /// Set the largest accepted request body
+    pub fn with_max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = Some(size);
+        self
+    }
+
+    /// Set the idle keep-alive timeout
+    pub fn with_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the deadline for receiving a complete request head
+    pub fn with_header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout of each request body read
+    pub fn with_body_read_timeout(mut self, timeout: Duration) -> Self {
+        self.body_read_timeout = Some(timeout);
+        self
+    }
+
+    /// Check the response headers in release builds too
+    pub fn with_strict_headers(mut self, strict: bool) -> Self {
+        self.strict_headers = strict;
+        self
+    }
+
+    /// Answer a panic of the service with `500 Internal Server Error`
+    pub fn with_catch_panics(mut self, catch: bool) -> Self {
+        self.catch_panics = catch;
+        self
+    }
+
+    /// Require a PROXY protocol header at the start of every connection
+    pub fn with_proxy_protocol(mut self, proxy_protocol: bool) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    /// Set the deadline for receiving the PROXY protocol header
+    pub fn with_proxy_header_timeout(mut self, timeout: Duration) -> Self {
+        self.proxy_header_timeout = timeout;
+        self
+    }
+}
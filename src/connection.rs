@@ -0,0 +1,333 @@
+//! per connection state shared by the connection loop and its requests
+
+use std::cell::{Cell, Ref, RefCell};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use bytes::BytesMut;
+
+use crate::config::HttpConfig;
+use crate::forwarded::TrustedProxies;
+use crate::listener::Stream;
+use crate::proxy_protocol::ProxyHeader;
+use crate::request::Framing;
+
+/// Facts about a client connection
+///
+/// Handed to [`HttpServiceFactory::new_service`](crate::HttpServiceFactory::new_service)
+/// when the connection is accepted and available to every request through
+/// [`Request::connection_info`](crate::Request::connection_info), for
+/// logging, per client limits or sticky routing.
+#[derive(Debug)]
+pub struct ConnectionInfo {
+    id: u64,
+    peer_addr: Option<SocketAddr>,
+    // the peer is the process on the other end of a Unix socket
+    unix_peer: bool,
+    local_addr: Option<SocketAddr>,
+    accepted_at: Instant,
+    requests: Cell<u64>,
+    proxy: Option<ProxyHeader>,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(id: u64, stream: &Stream) -> Self {
+        ConnectionInfo {
+            id,
+            peer_addr: stream.peer_addr(),
+            unix_peer: stream.is_unix(),
+            local_addr: stream.local_addr(),
+            accepted_at: Instant::now(),
+            requests: Cell::new(0),
+            proxy: None,
+        }
+    }
+
+    /// Number of the connection, counting up from `0` in accept order
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The address of the client, `None` for a Unix socket or if the OS could
+    /// not tell it
+    ///
+    /// Behind a load balancer speaking the PROXY protocol this is the
+    /// original client announced in the [`ProxyHeader`].
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The address the client connected to, `None` for a Unix socket or if
+    /// the OS could not tell it
+    ///
+    /// Behind a load balancer speaking the PROXY protocol this is the
+    /// original destination announced in the [`ProxyHeader`].
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// When the connection was accepted
+    pub fn accepted_at(&self) -> Instant {
+        self.accepted_at
+    }
+
+    /// Requests answered on this connection before the current one
+    pub fn requests_served(&self) -> u64 {
+        self.requests.get()
+    }
+
+    /// The PROXY protocol header the connection started with, see
+    /// [`HttpConfig::proxy_protocol`]
+    pub fn proxy_header(&self) -> Option<&ProxyHeader> {
+        self.proxy.as_ref()
+    }
+
+    /// Take the original addresses from the PROXY protocol header, a header
+    /// without addresses keeps the ones of the socket
+    pub(crate) fn set_proxy_header(&mut self, header: ProxyHeader) {
+        if let (Some(source), Some(destination)) = (header.source(), header.destination()) {
+            self.peer_addr = Some(source);
+            self.unix_peer = false;
+            self.local_addr = Some(destination);
+        }
+        self.proxy = Some(header);
+    }
+
+    /// Whether the peer is on a Unix socket rather than at
+    /// [`peer_addr`](Self::peer_addr)
+    #[inline]
+    pub(crate) fn is_unix_peer(&self) -> bool {
+        self.unix_peer
+    }
+
+    #[inline]
+    pub(crate) fn request_served(&self) {
+        self.requests.set(self.requests.get() + 1);
+    }
+}
+
+/// State that a request reports back to the connection loop
+///
+/// Lives on the connection coroutine's stack, so plain `Cell`s are enough.
+pub(crate) struct ConnState {
+    info: ConnectionInfo,
+    // peers whose forwarding headers are trusted
+    trusted_proxies: Arc<TrustedProxies>,
+    // longest request target accepted by the decoder
+    max_uri_len: usize,
+    // largest request head accepted by the decoder
+    max_head_size: usize,
+    // largest request body accepted, `usize::MAX` for no limit
+    max_body_size: usize,
+    // response heads are checked before they are written
+    check_headers: bool,
+    // framing of the last request body while the service has not asked for it
+    unread_body: Cell<Option<Framing>>,
+    // the end of the request body is unknown, the connection can't be reused
+    body_failed: Cell<bool>,
+    // the body read failed because it timed out
+    body_timed_out: Cell<bool>,
+    // the body read failed because the body exceeds its limit
+    body_too_large: Cell<bool>,
+    // the request being served is HTTP/1.0
+    http10: Cell<bool>,
+    // the request being served is a HEAD request
+    head: Cell<bool>,
+    // the client keeps the connection open after the request being served
+    keep_alive: Cell<bool>,
+    // head of the request being served, split off the request buffer so
+    // that reading the body can't move it
+    req_head: RefCell<BytesMut>,
+    // start and length of `METHOD path` in `req_head`, for errors and logs
+    request_line: Cell<(usize, usize)>,
+    // `100 Continue` is owed before the body of the request being served
+    expect_continue: Cell<bool>,
+    // the service panicked while serving the last request
+    panicked: Cell<bool>,
+}
+
+impl ConnState {
+    pub(crate) fn new(
+        config: &HttpConfig,
+        info: ConnectionInfo,
+        trusted_proxies: Arc<TrustedProxies>,
+    ) -> Self {
+        ConnState {
+            info,
+            trusted_proxies,
+            max_uri_len: config.max_uri_len,
+            max_head_size: config.max_head_size,
+            max_body_size: config.max_body_size.unwrap_or(usize::MAX),
+            check_headers: cfg!(debug_assertions) || config.strict_headers,
+            unread_body: Cell::new(None),
+            body_failed: Cell::new(false),
+            body_timed_out: Cell::new(false),
+            body_too_large: Cell::new(false),
+            http10: Cell::new(false),
+            head: Cell::new(false),
+            keep_alive: Cell::new(true),
+            req_head: RefCell::new(BytesMut::new()),
+            request_line: Cell::new((0, 0)),
+            expect_continue: Cell::new(false),
+            panicked: Cell::new(false),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    #[inline]
+    pub(crate) fn trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
+    }
+
+    #[inline]
+    pub(crate) fn max_uri_len(&self) -> usize {
+        self.max_uri_len
+    }
+
+    #[inline]
+    pub(crate) fn max_head_size(&self) -> usize {
+        self.max_head_size
+    }
+
+    /// Record the version, method and persistence of the request being served
+    #[inline]
+    pub(crate) fn set_request(&self, http10: bool, head: bool, keep_alive: bool) {
+        self.http10.set(http10);
+        self.head.set(head);
+        self.keep_alive.set(keep_alive);
+    }
+
+    /// Keep the head of the request being served until it is released
+    ///
+    /// `request_line` is the start and length of `METHOD path` in `head`.
+    #[inline]
+    pub(crate) fn set_head(&self, head: BytesMut, request_line: (usize, usize)) {
+        *self.req_head.borrow_mut() = head;
+        self.request_line.set(request_line);
+    }
+
+    /// Drop the head once the request is served, the request buffer can
+    /// then be reused in place
+    #[inline]
+    pub(crate) fn release_head(&self) {
+        *self.req_head.borrow_mut() = BytesMut::new();
+        self.request_line.set((0, 0));
+    }
+
+    /// `METHOD path` of the request being served
+    pub(crate) fn request_line(&self) -> Ref<'_, str> {
+        let (start, len) = self.request_line.get();
+        Ref::map(self.req_head.borrow(), |head| {
+            let line = head.get(start..start + len).unwrap_or_default();
+            std::str::from_utf8(line).unwrap_or_default()
+        })
+    }
+
+    #[inline]
+    pub(crate) fn is_http10(&self) -> bool {
+        self.http10.get()
+    }
+
+    #[inline]
+    pub(crate) fn is_head(&self) -> bool {
+        self.head.get()
+    }
+
+    #[inline]
+    pub(crate) fn keep_alive(&self) -> bool {
+        self.keep_alive.get()
+    }
+
+    #[inline]
+    pub(crate) fn set_expect_continue(&self, expect: bool) {
+        self.expect_continue.set(expect);
+    }
+
+    /// Whether `100 Continue` is owed before the body, the connection must
+    /// flush the earlier responses first
+    #[inline]
+    pub(crate) fn expects_continue(&self) -> bool {
+        self.expect_continue.get()
+    }
+
+    /// Whether `100 Continue` is owed before the body, clears the flag
+    #[inline]
+    pub(crate) fn take_expect_continue(&self) -> bool {
+        self.expect_continue.replace(false)
+    }
+
+    #[inline]
+    pub(crate) fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    #[inline]
+    pub(crate) fn check_headers(&self) -> bool {
+        self.check_headers
+    }
+
+    #[inline]
+    pub(crate) fn set_unread_body(&self, framing: Option<Framing>) {
+        self.unread_body.set(framing);
+    }
+
+    /// The framing of a body the service never asked for, clears it
+    #[inline]
+    pub(crate) fn take_unread_body(&self) -> Option<Framing> {
+        self.unread_body.take()
+    }
+
+    #[inline]
+    pub(crate) fn set_body_too_large(&self) {
+        self.body_too_large.set(true);
+    }
+
+    /// Whether the body of the last request exceeded its limit, clears the flag
+    #[inline]
+    pub(crate) fn take_body_too_large(&self) -> bool {
+        self.body_too_large.replace(false)
+    }
+
+    #[inline]
+    pub(crate) fn set_body_timed_out(&self) {
+        self.body_timed_out.set(true);
+    }
+
+    #[inline]
+    pub(crate) fn set_body_failed(&self) {
+        self.body_failed.set(true);
+    }
+
+    #[inline]
+    pub(crate) fn body_failed(&self) -> bool {
+        self.body_failed.get()
+    }
+
+    /// Whether a body read of the last request failed, clears the flag
+    #[inline]
+    pub(crate) fn take_body_failed(&self) -> bool {
+        self.body_failed.replace(false)
+    }
+
+    /// Whether the body read of the last request timed out, clears the flag
+    #[inline]
+    pub(crate) fn take_body_timed_out(&self) -> bool {
+        self.body_timed_out.replace(false)
+    }
+
+    #[inline]
+    pub(crate) fn set_panicked(&self) {
+        self.panicked.set(true);
+    }
+
+    /// Whether the service panicked on the last request, clears the flag
+    #[inline]
+    pub(crate) fn take_panicked(&self) -> bool {
+        self.panicked.replace(false)
+    }
+}
@@ -0,0 +1,323 @@
+//! errors the server answers on its own and the hook to customize them
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::headers::HeaderError;
+use crate::request::BodyError;
+use crate::response::Response;
+use crate::status::StatusCode;
+
+/// Reason a request head could not be decoded
+///
+/// The server answers these with the matching `4xx`/`5xx` status and closes
+/// the connection, see [`ErrorHandler::parse_error`] to customize the
+/// response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The request head is malformed, answered with `400 Bad Request`
+    BadRequest(httparse::Error),
+    /// More header lines than the configured
+    /// [`MaxHeaders`](crate::MaxHeaders), answered with
+    /// `431 Request Header Fields Too Large`
+    ///
+    /// Consider `MaxHeaders::Standard` (32), `MaxHeaders::Large` (64) or
+    /// `MaxHeaders::XLarge` (128) for services behind proxies.
+    TooManyHeaders {
+        /// Header lines found in the request head
+        received: usize,
+        /// The configured limit
+        limit: usize,
+    },
+    /// The request head is larger than
+    /// [`HttpConfig::max_head_size`](crate::HttpConfig::max_head_size),
+    /// answered with `431 Request Header Fields Too Large`
+    HeadTooLarge {
+        /// The configured limit
+        limit: usize,
+    },
+    /// The request target is longer than
+    /// [`HttpConfig::max_uri_len`](crate::HttpConfig::max_uri_len),
+    /// answered with `414 URI Too Long`
+    UriTooLong {
+        /// The configured limit
+        limit: usize,
+    },
+    /// A well formed `HTTP/x.y` version other than 1.0 and 1.1, answered
+    /// with `505 HTTP Version Not Supported`
+    VersionNotSupported,
+    /// A `Content-Length` that is not a number, overflows or conflicts with
+    /// another one, answered with `400 Bad Request`
+    InvalidContentLength,
+    /// `Transfer-Encoding` whose final coding is not `chunked`, answered
+    /// with `400 Bad Request`
+    InvalidTransferEncoding,
+    /// Both `Content-Length` and `Transfer-Encoding` are present, answered
+    /// with `400 Bad Request`
+    ContentLengthWithTransferEncoding,
+}
+
+impl ParseError {
+    /// The status of the response sent for this error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::BadRequest(_)
+            | ParseError::InvalidContentLength
+            | ParseError::InvalidTransferEncoding
+            | ParseError::ContentLengthWithTransferEncoding => StatusCode::BAD_REQUEST,
+            ParseError::TooManyHeaders { .. } | ParseError::HeadTooLarge { .. } => {
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            }
+            ParseError::UriTooLong { .. } => StatusCode::URI_TOO_LONG,
+            ParseError::VersionNotSupported => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+        }
+    }
+
+    /// The reason phrase of the response sent for this error
+    pub fn reason(&self) -> &'static str {
+        // every status above is registered
+        self.status().canonical_reason().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::BadRequest(e) => write!(f, "failed to parse http request: {e}"),
+            ParseError::TooManyHeaders { received, limit } => write!(
+                f,
+                "too many headers: received {received}, limit is {limit} (over by {})",
+                received.saturating_sub(*limit)
+            ),
+            ParseError::HeadTooLarge { limit } => {
+                write!(f, "request head larger than {limit} bytes")
+            }
+            ParseError::UriTooLong { limit } => {
+                write!(f, "request target longer than {limit} bytes")
+            }
+            ParseError::VersionNotSupported => f.write_str("http version not supported"),
+            ParseError::InvalidContentLength => f.write_str("invalid content-length"),
+            ParseError::InvalidTransferEncoding => {
+                f.write_str("transfer-encoding does not end with chunked")
+            }
+            ParseError::ContentLengthWithTransferEncoding => {
+                f.write_str("both content-length and transfer-encoding present")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// An error that picks the status of the response
+///
+/// Converts into an [`io::Error`], so a service can return it with `?`. The
+/// message is meant for the client and becomes the body of the default
+/// response, the text of any other error is only logged.
+///
+/// # Examples
+///
+/// ```
+/// use may_minihttp::{HttpError, HttpService, Request, Response, StatusCode};
+/// use std::io;
+///
+/// struct Items;
+///
+/// impl HttpService for Items {
+///     fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+///         let Some(id) = req.query_param("id") else {
+///             return Err(HttpError::with_message(StatusCode::BAD_REQUEST, "missing id").into());
+///         };
+///         let id: u64 = id.parse().map_err(|_| HttpError::new(StatusCode::BAD_REQUEST))?;
+///         rsp.body_vec(format!("item {id}").into_bytes());
+///         Ok(())
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    status: StatusCode,
+    message: Option<Cow<'static, str>>,
+}
+
+impl HttpError {
+    /// An error answered with `status` and an empty body
+    pub fn new(status: StatusCode) -> Self {
+        HttpError {
+            status,
+            message: None,
+        }
+    }
+
+    /// An error answered with `status` and `message` as a plain text body
+    pub fn with_message(status: StatusCode, message: impl Into<Cow<'static, str>>) -> Self {
+        HttpError {
+            status,
+            message: Some(message.into()),
+        }
+    }
+
+    /// The status of the response
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The message for the client
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// The `HttpError` that `err` was converted from
+    pub fn from_io(err: &io::Error) -> Option<&HttpError> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+impl From<StatusCode> for HttpError {
+    fn from(status: StatusCode) -> Self {
+        HttpError::new(status)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}: {message}", self.status),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+impl Error for HttpError {}
+
+impl From<HttpError> for io::Error {
+    fn from(e: HttpError) -> Self {
+        io::Error::other(e)
+    }
+}
+
+/// The status a failed service call is answered with
+///
+/// An [`HttpError`] picks its own, an invalid request header or body is the
+/// client's fault and anything else is `500 Internal Server Error`.
+pub(crate) fn error_status(err: &io::Error) -> StatusCode {
+    let Some(inner) = err.get_ref() else {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    };
+    if let Some(e) = inner.downcast_ref::<HttpError>() {
+        e.status()
+    } else if inner.is::<HeaderError>() || inner.is::<BodyError>() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Method and target of the request a service failed on
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLine<'a> {
+    method: &'a str,
+    path: &'a str,
+}
+
+impl<'a> RequestLine<'a> {
+    /// Split a `METHOD path` line
+    pub(crate) fn new(line: &'a str) -> Self {
+        let (method, path) = line.split_once(' ').unwrap_or((line, ""));
+        RequestLine { method, path }
+    }
+
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// The raw request target, including the query string
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+}
+
+impl fmt::Display for RequestLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.path)
+    }
+}
+
+/// Hook to customize the responses the server writes on its own
+///
+/// Every method has a default that keeps the plain response, so implement
+/// only the ones you need and install the handler with
+/// [`ServerBuilder::error_handler`](crate::ServerBuilder::error_handler).
+///
+/// # Examples
+///
+/// ```no_run
+/// use may_minihttp::{ErrorHandler, HttpServer, ParseError, Response, ServerBuilder};
+/// # use may_minihttp::{HttpService, Request};
+/// # #[derive(Clone)]
+/// # struct Hello;
+/// # impl HttpService for Hello {
+/// #     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+/// #         Ok(())
+/// #     }
+/// # }
+///
+/// struct JsonErrors;
+///
+/// impl ErrorHandler for JsonErrors {
+///     fn parse_error(&self, err: &ParseError, rsp: &mut Response) {
+///         rsp.header("Content-Type: application/json");
+///         rsp.body_vec(format!(r#"{{"error":"{}"}}"#, err.reason()).into_bytes());
+///     }
+/// }
+///
+/// let server = ServerBuilder::new(HttpServer(Hello))
+///     .error_handler(JsonErrors)
+///     .bind("127.0.0.1:8080")
+///     .unwrap();
+/// ```
+pub trait ErrorHandler: Send + Sync + 'static {
+    /// Called before answering a request that could not be decoded
+    ///
+    /// `rsp` already carries the status for `err` and an empty body. The
+    /// connection is closed after the response is written.
+    fn parse_error(&self, err: &ParseError, rsp: &mut Response) {
+        let _ = (err, rsp);
+    }
+
+    /// Called before answering a request whose service panicked, see
+    /// [`HttpConfig::catch_panics`](crate::HttpConfig::catch_panics)
+    ///
+    /// `rsp` is reset to `500 Internal Server Error` with no headers and an
+    /// empty body, the panic is already logged. Not called if the service
+    /// started streaming the body, the connection is closed instead.
+    fn panic(&self, rsp: &mut Response) {
+        let _ = rsp;
+    }
+
+    /// Called before answering a request whose service returned an error
+    ///
+    /// `rsp` is reset to the status picked for `err`, see [`HttpError`]. The
+    /// message of an `HttpError` is set as a plain text body, any other
+    /// error leaves the body empty. The error is already logged, don't copy
+    /// its text into the body unless it is meant for the client. Not called
+    /// if the service started streaming the body, the connection is closed
+    /// instead.
+    fn service_error(&self, err: &io::Error, req: RequestLine<'_>, rsp: &mut Response) {
+        let _ = (err, req, rsp);
+    }
+}
+
+/// Keeps the plain responses
+pub(crate) struct DefaultErrorHandler;
+
+impl ErrorHandler for DefaultErrorHandler {}
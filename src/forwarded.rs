@@ -0,0 +1,354 @@
+//! the client of a request that passed through trusted proxies
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use smallvec::SmallVec;
+
+use crate::request::Request;
+
+/// A network of IP addresses, like `10.0.0.0/8` or `2001:db8::/32`
+///
+/// Tells the server which peers are proxies whose forwarding headers are
+/// trusted, see [`ServerBuilder::trusted_proxies`](crate::ServerBuilder::trusted_proxies).
+/// An address without a prefix length is a network of its own.
+///
+/// # Examples
+///
+/// ```
+/// use may_minihttp::IpNet;
+///
+/// let net: IpNet = "10.0.0.0/8".parse().unwrap();
+/// assert!(net.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!net.contains("192.168.0.1".parse().unwrap()));
+///
+/// let host: IpNet = "::1".parse().unwrap();
+/// assert_eq!(host.prefix_len(), 128);
+/// assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// A string that is not an IP network, or a prefix longer than the address
+///
+/// Converts into an [`io::ErrorKind::InvalidInput`] error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidIpNet;
+
+impl fmt::Display for InvalidIpNet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid IP network")
+    }
+}
+
+impl Error for InvalidIpNet {}
+
+impl From<InvalidIpNet> for io::Error {
+    fn from(e: InvalidIpNet) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+impl IpNet {
+    /// The network of `addr` with a `prefix` bits long mask, the host bits
+    /// of `addr` are cleared
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, InvalidIpNet> {
+        let addr = match addr {
+            IpAddr::V4(ip) if prefix <= 32 => IpAddr::V4(
+                (u32::from(ip) & u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)).into(),
+            ),
+            IpAddr::V6(ip) if prefix <= 128 => IpAddr::V6(
+                (u128::from(ip) & u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0))
+                    .into(),
+            ),
+            _ => return Err(InvalidIpNet),
+        };
+        Ok(IpNet { addr, prefix })
+    }
+
+    /// The first address of the network
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The number of leading bits shared by the addresses of the network
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether `ip` is in the network, an IPv4-mapped IPv6 address counts as
+    /// the IPv4 address
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match ip.to_canonical() {
+            ip if ip.is_ipv4() != self.addr.is_ipv4() => false,
+            ip => IpNet::new(ip, self.prefix).is_ok_and(|net| net.addr == self.addr),
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = InvalidIpNet;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.split_once('/').unwrap_or((s, ""));
+        let addr = addr.parse::<IpAddr>().map_err(|_| InvalidIpNet)?;
+        let prefix = match prefix {
+            "" if !s.contains('/') => match addr {
+                IpAddr::V4(_) => 32,
+                IpAddr::V6(_) => 128,
+            },
+            prefix if prefix.bytes().all(|b| b.is_ascii_digit()) => {
+                prefix.parse().map_err(|_| InvalidIpNet)?
+            }
+            _ => return Err(InvalidIpNet),
+        };
+        IpNet::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The forwarding headers the trusted proxies in front of the server set
+///
+/// Only this family is followed, see
+/// [`ServerBuilder::forwarded_headers`](crate::ServerBuilder::forwarded_headers).
+/// The proxies don't remove the other one from the requests they pass on,
+/// so a client could add it to pick its own address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ForwardedHeaders {
+    /// `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`, and
+    /// `X-Real-IP` when there is no `X-Forwarded-For`
+    #[default]
+    XForwarded,
+    /// The standard `Forwarded` header of RFC 7239
+    Forwarded,
+}
+
+/// The peers whose forwarding headers are trusted
+#[derive(Default)]
+pub(crate) struct TrustedProxies {
+    pub(crate) nets: Vec<IpNet>,
+    /// The headers the proxies set
+    pub(crate) headers: ForwardedHeaders,
+    /// Peers on a Unix socket, which have no address
+    pub(crate) unix_peers: bool,
+}
+
+impl TrustedProxies {
+    #[inline]
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(ip))
+    }
+}
+
+/// What the trusted proxies in front of the server say about a request
+pub(crate) struct Forwarded<'a> {
+    /// The first address of the chain that is not a trusted proxy, `None`
+    /// if a proxy on a Unix socket did not name one
+    pub(crate) client: Option<IpAddr>,
+    /// The scheme the client used, as the proxy next to it saw it
+    pub(crate) proto: Option<&'a str>,
+    /// The host the client asked for, as the proxy next to it saw it
+    pub(crate) host: Option<&'a str>,
+    /// Whether it is from `Forwarded` rather than the `X-Forwarded-*` headers
+    pub(crate) standard: bool,
+}
+
+/// Follow the forwarding headers of a request that arrived from `peer`
+///
+/// The chain is walked from the server towards the client as long as the
+/// hops are trusted proxies, the entries left of the first untrusted one
+/// could be made up by the client. An entry without an address, like
+/// `unknown` or an obfuscated node, ends the walk at the proxy that added
+/// it. Only the configured header family is looked at, and `X-Real-IP` is
+/// only used without `X-Forwarded-For`.
+///
+/// Returns `None` if the peer is not a trusted proxy. `peer` is its
+/// address, a peer on a Unix socket has none.
+pub(crate) fn resolve<'a>(
+    req: &'a Request,
+    peer: Option<IpAddr>,
+    unix_peer: bool,
+    trusted: &TrustedProxies,
+) -> Option<Forwarded<'a>> {
+    let is_trusted = |ip: IpAddr| trusted.contains(ip);
+    let trusted_peer = match unix_peer {
+        true => trusted.unix_peers,
+        false => peer.is_some_and(is_trusted),
+    };
+    if !trusted_peer {
+        return None;
+    }
+    let mut hop = Forwarded {
+        client: peer,
+        proto: None,
+        host: None,
+        standard: trusted.headers == ForwardedHeaders::Forwarded,
+    };
+
+    if hop.standard {
+        let elements: SmallVec<[&[u8]; 4]> = req.header_list("forwarded").collect();
+        for element in elements.iter().rev() {
+            let Ok(element) = std::str::from_utf8(element) else {
+                break;
+            };
+            let mut node = None;
+            hop.proto = None;
+            hop.host = None;
+            for (name, value) in pairs(element) {
+                if name.eq_ignore_ascii_case("for") {
+                    node = Some(value);
+                } else if name.eq_ignore_ascii_case("proto") {
+                    hop.proto = Some(value);
+                } else if name.eq_ignore_ascii_case("host") {
+                    hop.host = Some(value);
+                }
+            }
+            let Some(ip) = node.and_then(parse_node) else {
+                break;
+            };
+            hop.client = Some(ip);
+            if !is_trusted(ip) {
+                break;
+            }
+        }
+        return Some(hop);
+    }
+
+    let chain: SmallVec<[&[u8]; 4]> = req.header_list("x-forwarded-for").collect();
+    // entries of the chain looked at, the proto and host are the ones added
+    // along with the last of them
+    let mut walked = 0;
+    for node in chain.iter().rev() {
+        walked += 1;
+        let Some(ip) = to_str(node).and_then(parse_node) else {
+            break;
+        };
+        hop.client = Some(ip);
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    if chain.is_empty() {
+        if let Some(ip) = req
+            .header("x-real-ip")
+            .and_then(to_str)
+            .and_then(parse_node)
+        {
+            hop.client = Some(ip);
+        }
+    }
+    let nth_from_end = walked.max(1) - 1;
+    hop.proto = pick(req.header_list("x-forwarded-proto"), nth_from_end);
+    hop.host = pick(req.header_list("x-forwarded-host"), nth_from_end);
+    Some(hop)
+}
+
+#[inline]
+fn to_str(value: &[u8]) -> Option<&str> {
+    std::str::from_utf8(value).ok()
+}
+
+/// The `n`th list element from the end, the first one if the list is shorter
+///
+/// A proxy that only passes the header through leaves a single element set by
+/// the proxy next to the client.
+fn pick<'a>(items: impl Iterator<Item = &'a [u8]>, n: usize) -> Option<&'a str> {
+    let items: SmallVec<[&[u8]; 4]> = items.collect();
+    let item = match items.len().checked_sub(n + 1) {
+        Some(i) => items[i],
+        None => *items.first()?,
+    };
+    to_str(item)
+}
+
+/// The `name=value` pairs of a `Forwarded` element, quoted values are
+/// unquoted
+fn pairs(element: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut rest = element;
+    std::iter::from_fn(move || loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            return None;
+        }
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c == ';' && !quoted
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let pair = &rest[..end];
+        rest = &rest[end..];
+        if let Some((name, value)) = pair.split_once('=') {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            return Some((name.trim(), value));
+        }
+    })
+}
+
+/// The address of a node, `1.2.3.4`, `2001:db8::1` or either with a port,
+/// IPv6 in brackets then; `None` for `unknown` and obfuscated nodes
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Some(ip) = node.strip_prefix('[') {
+        // the port, if any, may be obfuscated
+        return ip.split_once(']')?.0.parse().ok();
+    }
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    node.split_once(':')?
+        .0
+        .parse::<Ipv4Addr>()
+        .ok()
+        .map(IpAddr::V4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_addresses() {
+        let v4: IpAddr = "192.0.2.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(parse_node("192.0.2.1"), Some(v4));
+        assert_eq!(parse_node(" 192.0.2.1:8080 "), Some(v4));
+        assert_eq!(parse_node("2001:db8::1"), Some(v6));
+        assert_eq!(parse_node("[2001:db8::1]"), Some(v6));
+        assert_eq!(parse_node("[2001:db8::1]:_port"), Some(v6));
+    }
+
+    /// Unknown and obfuscated nodes have no address
+    #[test]
+    fn node_without_address() {
+        for node in [
+            "unknown",
+            "_hidden",
+            "[_hidden]",
+            "[2001:db8::1",
+            "host:80",
+            "",
+        ] {
+            assert_eq!(parse_node(node), None, "{node}");
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! typed values of common request headers
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops;
+
+/// A request header whose value could not be parsed
+///
+/// Converts into an [`io::ErrorKind::InvalidData`] error, so services can
+/// use `?` on the typed accessors of [`Request`](crate::Request).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderError {
+    name: &'static str,
+}
+
+impl HeaderError {
+    pub(crate) fn new(name: &'static str) -> Self {
+        HeaderError { name }
+    }
+
+    /// The name of the invalid header
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} header", self.name)
+    }
+}
+
+impl Error for HeaderError {}
+
+impl From<HeaderError> for io::Error {
+    fn from(e: HeaderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Iterator over the elements of a comma separated header value
+///
+/// Commas inside quoted strings don't split, elements are trimmed and empty
+/// ones skipped.
+pub(crate) struct ListItems<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> ListItems<'a> {
+    pub(crate) fn new(value: &'a [u8]) -> Self {
+        ListItems { rest: value }
+    }
+}
+
+impl<'a> Iterator for ListItems<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        while !self.rest.is_empty() {
+            let mut quoted = false;
+            let mut escaped = false;
+            let end = self
+                .rest
+                .iter()
+                .position(|&b| {
+                    match b {
+                        _ if escaped => escaped = false,
+                        b'\\' if quoted => escaped = true,
+                        b'"' => quoted = !quoted,
+                        b',' if !quoted => return true,
+                        _ => {}
+                    }
+                    false
+                })
+                .unwrap_or(self.rest.len());
+            let item = self.rest[..end].trim_ascii();
+            self.rest = self.rest.get(end + 1..).unwrap_or_default();
+            if !item.is_empty() {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+/// The value of the only field named `name`, trimmed
+pub(crate) fn single<'a>(
+    name: &'static str,
+    mut values: impl Iterator<Item = &'a [u8]>,
+) -> Result<Option<&'a str>, HeaderError> {
+    let Some(value) = values.next() else {
+        return Ok(None);
+    };
+    if values.next().is_some() {
+        return Err(HeaderError::new(name));
+    }
+    to_str(name, value).map(Some)
+}
+
+pub(crate) fn to_str<'a>(name: &'static str, value: &'a [u8]) -> Result<&'a str, HeaderError> {
+    std::str::from_utf8(value.trim_ascii()).map_err(|_| HeaderError::new(name))
+}
+
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Split a quoted string off the start of `s`, returns the unescaped content
+/// and the rest
+fn quoted_string(s: &str) -> Option<(Cow<'_, str>, &str)> {
+    let body = s.strip_prefix('"')?;
+    let mut escaped = false;
+    let mut has_escapes = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => {
+                escaped = true;
+                has_escapes = true;
+            }
+            '"' => {
+                let content = &body[..i];
+                let content = if has_escapes {
+                    let mut out = String::with_capacity(content.len());
+                    let mut chars = content.chars();
+                    while let Some(c) = chars.next() {
+                        out.extend(if c == '\\' { chars.next() } else { Some(c) });
+                    }
+                    Cow::Owned(out)
+                } else {
+                    Cow::Borrowed(content)
+                };
+                return Some((content, &body[i + 1..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The `Host` header, split into the host name and the port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Host<'a> {
+    host: &'a str,
+    port: Option<u16>,
+}
+
+impl<'a> Host<'a> {
+    pub(crate) fn parse(value: &'a str) -> Option<Self> {
+        let (host, port) = if value.starts_with('[') {
+            // IPv6 literal
+            let end = value.find(']')?;
+            (&value[..=end], &value[end + 1..])
+        } else {
+            value.rfind(':').map_or((value, ""), |i| value.split_at(i))
+        };
+        let port = match port {
+            "" => None,
+            port => Some(port.strip_prefix(':')?.parse().ok()?),
+        };
+        if host
+            .bytes()
+            .any(|b| b.is_ascii_whitespace() || b == b'/' || b == b'@')
+        {
+            return None;
+        }
+        Some(Host { host, port })
+    }
+
+    /// The host name or address, IPv6 addresses keep their brackets
+    pub fn host(&self) -> &'a str {
+        self.host
+    }
+
+    /// The port, if the header has one
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+/// A media type of `Content-Type` or a media range of `Accept`
+///
+/// The type, subtype and parameter names compare case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType<'a> {
+    essence: &'a str,
+    slash: usize,
+    params: Vec<(&'a str, Cow<'a, str>)>,
+}
+
+impl<'a> MediaType<'a> {
+    pub(crate) fn parse(s: &'a str) -> Option<Self> {
+        let (essence, mut rest) = s.split_once(';').unwrap_or((s, ""));
+        let essence = essence.trim();
+        let slash = essence.find('/')?;
+        if !is_token(&essence[..slash]) || !is_token(&essence[slash + 1..]) {
+            return None;
+        }
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ';']);
+            if rest.is_empty() {
+                break;
+            }
+            let (name, value) = rest.split_once('=')?;
+            let name = name.trim_end();
+            if !is_token(name) {
+                return None;
+            }
+            let (value, after) = if value.starts_with('"') {
+                quoted_string(value)?
+            } else {
+                let end = value.find(';').unwrap_or(value.len());
+                let token = value[..end].trim_end();
+                if !is_token(token) {
+                    return None;
+                }
+                (Cow::Borrowed(token), &value[end..])
+            };
+            params.push((name, value));
+            rest = after.trim_start();
+            if !rest.is_empty() && !rest.starts_with(';') {
+                return None;
+            }
+        }
+        Some(MediaType {
+            essence,
+            slash,
+            params,
+        })
+    }
+
+    /// `type/subtype` without the parameters
+    pub fn essence(&self) -> &'a str {
+        self.essence
+    }
+
+    /// The top level type, `text` of `text/html`
+    pub fn main_type(&self) -> &'a str {
+        &self.essence[..self.slash]
+    }
+
+    /// The subtype, `html` of `text/html`
+    pub fn subtype(&self) -> &'a str {
+        &self.essence[self.slash + 1..]
+    }
+
+    /// Whether the essence is `essence`, ignoring case
+    pub fn is(&self, essence: &str) -> bool {
+        self.essence.eq_ignore_ascii_case(essence)
+    }
+
+    /// The value of the parameter `name`, unquoted
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+
+    /// The parameters in order
+    pub fn params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(n, v)| (*n, &**v))
+    }
+
+    /// The `charset` parameter
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    /// The `q` weight of an `Accept` media range in thousandths, `1000` when
+    /// absent and `0` when malformed
+    pub fn quality(&self) -> u16 {
+        self.param("q").map_or(1000, parse_quality)
+    }
+}
+
+/// Parse a qvalue, `0.5` is 500
+fn parse_quality(q: &str) -> u16 {
+    let (int, frac) = q.split_once('.').unwrap_or((q, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return 0;
+    }
+    let frac = frac.bytes().chain(b"000".iter().copied()).take(3);
+    let frac = frac.fold(0, |n, b| n * 10 + u16::from(b - b'0'));
+    match int {
+        "0" => frac,
+        "1" if frac == 0 => 1000,
+        _ => 0,
+    }
+}
+
+/// The `Authorization` header, split into the scheme and the credentials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Authorization<'a> {
+    scheme: &'a str,
+    credentials: &'a str,
+}
+
+impl<'a> Authorization<'a> {
+    pub(crate) fn parse(value: &'a str) -> Option<Self> {
+        let (scheme, credentials) = value.split_once(' ').unwrap_or((value, ""));
+        if !is_token(scheme) {
+            return None;
+        }
+        Some(Authorization {
+            scheme,
+            credentials: credentials.trim(),
+        })
+    }
+
+    /// The authentication scheme, like `Basic` or `Bearer`
+    pub fn scheme(&self) -> &'a str {
+        self.scheme
+    }
+
+    /// Everything after the scheme
+    pub fn credentials(&self) -> &'a str {
+        self.credentials
+    }
+
+    /// The token of the `Bearer` scheme
+    pub fn bearer(&self) -> Option<&'a str> {
+        self.scheme
+            .eq_ignore_ascii_case("bearer")
+            .then_some(self.credentials)
+    }
+}
+
+/// Connection options of the `Connection` header
+///
+/// Only the options the server acts on are kept, the others are available
+/// through [`Request::header_list`](crate::Request::header_list).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Connection {
+    close: bool,
+    keep_alive: bool,
+    upgrade: bool,
+}
+
+impl Connection {
+    pub(crate) fn add(&mut self, option: &[u8]) {
+        if option.eq_ignore_ascii_case(b"close") {
+            self.close = true;
+        } else if option.eq_ignore_ascii_case(b"keep-alive") {
+            self.keep_alive = true;
+        } else if option.eq_ignore_ascii_case(b"upgrade") {
+            self.upgrade = true;
+        }
+    }
+
+    /// The client closes the connection after this request
+    pub fn is_close(&self) -> bool {
+        self.close
+    }
+
+    /// The client asks to keep the connection open
+    pub fn is_keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// The client asks to switch protocols
+    pub fn is_upgrade(&self) -> bool {
+        self.upgrade
+    }
+}
+
+/// An entity tag of `If-None-Match` or an `ETag` response header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityTag<'a> {
+    weak: bool,
+    tag: &'a str,
+}
+
+impl<'a> EntityTag<'a> {
+    /// A strong tag, `tag` is without the quotes
+    pub fn strong(tag: &'a str) -> Self {
+        EntityTag { weak: false, tag }
+    }
+
+    /// A weak tag, `tag` is without the quotes
+    pub fn weak(tag: &'a str) -> Self {
+        EntityTag { weak: true, tag }
+    }
+
+    /// Parse the quoted form, `"xyz"` or `W/"xyz"`
+    pub fn parse(s: &'a str) -> Option<Self> {
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, s),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(EntityTag { weak, tag })
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The tag without the quotes and the weak prefix
+    pub fn tag(&self) -> &'a str {
+        self.tag
+    }
+
+    /// Weak comparison, equal tags match whether or not they are weak
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Strong comparison, only equal strong tags match
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+}
+
+impl fmt::Display for EntityTag<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+/// The `If-None-Match` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfNoneMatch<'a> {
+    /// `*`, matches any current representation
+    Any,
+    /// A list of entity tags
+    Tags(Vec<EntityTag<'a>>),
+}
+
+impl IfNoneMatch<'_> {
+    /// Whether the representation tagged `etag` matches, in which case a
+    /// `GET` is answered with `304 Not Modified`
+    pub fn matches(&self, etag: &EntityTag) -> bool {
+        match self {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Tags(tags) => tags.iter().any(|t| t.weak_eq(etag)),
+        }
+    }
+}
+
+/// One range of a `Range: bytes=...` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both inclusive
+    FromTo(u64, u64),
+    /// `first-`, up to the end
+    From(u64),
+    /// `-n`, the last `n` bytes
+    Last(u64),
+}
+
+impl ByteRange {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let (first, last) = s.split_once('-')?;
+        let num = |s: &str| {
+            let s = s.trim();
+            if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            s.parse::<u64>().ok()
+        };
+        match (first.trim(), last.trim()) {
+            ("", last) => Some(ByteRange::Last(num(last)?)),
+            (first, "") => Some(ByteRange::From(num(first)?)),
+            (first, last) => {
+                let (first, last) = (num(first)?, num(last)?);
+                (first <= last).then_some(ByteRange::FromTo(first, last))
+            }
+        }
+    }
+
+    /// The byte offsets selected in a representation of `len` bytes, `None`
+    /// if the range is not satisfiable
+    pub fn resolve(&self, len: u64) -> Option<ops::Range<u64>> {
+        let range = match *self {
+            ByteRange::FromTo(first, last) => first..len.min(last.saturating_add(1)),
+            ByteRange::From(first) => first..len,
+            ByteRange::Last(n) => len.saturating_sub(n)..len,
+        };
+        (range.start < range.end).then_some(range)
+    }
+}
+
+/// Parse a `Range` header value, `None` for another unit than `bytes`
+pub(crate) fn parse_range(value: &str) -> Result<Option<Vec<ByteRange>>, HeaderError> {
+    let invalid = || HeaderError::new("Range");
+    let (unit, ranges) = value.split_once('=').ok_or_else(invalid)?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Ok(None);
+    }
+    let ranges = ListItems::new(ranges.as_bytes())
+        .map(|r| std::str::from_utf8(r).ok().and_then(ByteRange::parse))
+        .collect::<Option<Vec<_>>>()
+        .filter(|ranges| !ranges.is_empty())
+        .ok_or_else(invalid)?;
+    Ok(Some(ranges))
+}
+
+/// Iterator over the `name=value` pairs of the `Cookie` headers
+///
+/// Pairs without `=` and headers that are not valid UTF-8 are skipped.
+pub struct Cookies<'a> {
+    headers: std::slice::Iter<'a, httparse::Header<'a>>,
+    rest: &'a str,
+}
+
+impl<'a> Cookies<'a> {
+    pub(crate) fn new(headers: &'a [httparse::Header<'a>]) -> Self {
+        Cookies {
+            headers: headers.iter(),
+            rest: "",
+        }
+    }
+}
+
+impl<'a> Iterator for Cookies<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.rest.is_empty() {
+                let header = self.headers.next()?;
+                if header.name.eq_ignore_ascii_case("cookie") {
+                    self.rest = std::str::from_utf8(header.value).unwrap_or_default();
+                }
+            }
+            let (pair, rest) = self.rest.split_once(';').unwrap_or((self.rest, ""));
+            self.rest = rest;
+            if let Some((name, value)) = pair.split_once('=') {
+                return Some((name.trim(), value.trim()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_type_with_parameters() {
+        let mt = MediaType::parse("Text/HTML ; charset=\"utf-8\";q=0.5").unwrap();
+        assert_eq!(mt.essence(), "Text/HTML");
+        assert_eq!((mt.main_type(), mt.subtype()), ("Text", "HTML"));
+        assert!(mt.is("text/html"));
+        assert_eq!(mt.charset(), Some("utf-8"));
+        assert_eq!(mt.quality(), 500);
+        assert_eq!(
+            mt.params().collect::<Vec<_>>(),
+            [("charset", "utf-8"), ("q", "0.5")]
+        );
+    }
+
+    /// Quoted values are unescaped and may contain separators
+    #[test]
+    fn media_type_quoted_parameter() {
+        let mt = MediaType::parse(r#"multipart/form-data; boundary="a\"b;c""#).unwrap();
+        assert_eq!(mt.param("BOUNDARY"), Some("a\"b;c"));
+        assert_eq!(MediaType::parse("text/plain;").unwrap().params().count(), 0);
+    }
+
+    #[test]
+    fn invalid_media_types() {
+        for s in [
+            "text",
+            "te xt/html",
+            "text/",
+            "text/html; charset",
+            "text/html; a=b c",
+            "text/html; a=\"open",
+            "text/html; a=\"b\"c",
+        ] {
+            assert!(MediaType::parse(s).is_none(), "{s}");
+        }
+    }
+}
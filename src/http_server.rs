@@ -2,17 +2,27 @@
 
 use std::io::{self, Read, Write};
 use std::mem::MaybeUninit;
-use std::net::ToSocketAddrs;
+use std::net::{Shutdown, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::request::{self, Request};
+use crate::config::{HttpConfig, DEFAULT_BUF_SIZE};
+use crate::connection::{ConnState, ConnectionInfo};
+use crate::error::{self, ErrorHandler, HttpError, ParseError, RequestLine};
+use crate::forwarded::TrustedProxies;
+use crate::listener::{BoundAddr, Listener, Stream};
+use crate::proxy_protocol;
+use crate::request::{self, MaxHeaders, Request};
 use crate::response::{self, Response};
+use crate::server_builder::ServerBuilder;
+use crate::server_handle::{ConnGuard, Counters, ServerHandle, ServerState};
+use crate::status::StatusCode;
 
 #[cfg(unix)]
 use bytes::Buf;
 use bytes::{BufMut, BytesMut};
-#[cfg(unix)]
-use may::io::WaitIo;
-use may::net::{TcpListener, TcpStream};
+use may::sync::mpsc;
 use may::{coroutine, go};
 
 /// Check if an error is a normal client disconnect (not worth logging as ERROR)
@@ -28,6 +38,16 @@ fn is_client_disconnect(e: &io::Error) -> bool {
     )
 }
 
+/// Check if an error is a read timeout, reported as `TimedOut` or as
+/// `WouldBlock` depending on the platform
+#[inline]
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
 macro_rules! t_c {
     ($e: expr) => {
         match $e {
@@ -49,44 +69,179 @@ pub trait HttpService {
 
 pub trait HttpServiceFactory: Send + Sized + 'static {
     type Service: HttpService + Send;
-    // create a new http service for each connection
-    fn new_service(&self, id: usize) -> Self::Service;
+    /// Create the service of a newly accepted connection
+    fn new_service(&self, conn: &ConnectionInfo) -> Self::Service;
 
     /// Spawns the http service, binding to the given address
-    /// return a coroutine that you can cancel it when need to stop the service
-    fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
-        let listener = TcpListener::bind(addr)?;
-        go!(
-            coroutine::Builder::new().name("TcpServerFac".to_owned()),
-            move || {
-                #[cfg(unix)]
-                use std::os::fd::AsRawFd;
-                #[cfg(windows)]
-                use std::os::windows::io::AsRawSocket;
-                for stream in listener.incoming() {
-                    let mut stream = t_c!(stream);
-                    #[cfg(unix)]
-                    let id = stream.as_raw_fd() as usize;
-                    #[cfg(windows)]
-                    let id = stream.as_raw_socket() as usize;
-                    // t_c!(stream.set_nodelay(true));
-                    let service = self.new_service(id);
-                    let builder = may::coroutine::Builder::new().id(id);
-                    go!(
-                        builder,
-                        move || if let Err(e) = each_connection_loop(&mut stream, service) {
-                            // Only log actual errors, not normal client disconnects
-                            if !is_client_disconnect(&e) {
-                                error!("service err = {e:?}");
-                            }
-                            stream.shutdown(std::net::Shutdown::Both).ok();
-                        }
-                    )
-                    .unwrap();
+    /// return a [`ServerHandle`] that you can use to stop the service
+    fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
+        ServerBuilder::new(self).bind(addr)
+    }
+}
+
+/// Spawns the accept loop for an already bound listener
+///
+/// Every accepted connection gets a fresh service from the factory and runs
+/// the connection loop with the given configuration.
+pub(crate) fn serve<F: HttpServiceFactory>(
+    listener: Listener,
+    factory: F,
+    config: HttpConfig,
+    errors: Arc<dyn ErrorHandler>,
+    proxies: Arc<TrustedProxies>,
+) -> io::Result<ServerHandle> {
+    let addr = listener.bound_addr()?;
+    let state = Arc::new(ServerState::default());
+    if config.proxy_protocol {
+        return serve_proxied(listener, factory, config, errors, proxies, state, addr);
+    }
+    let server = state.clone();
+    let accept = go!(
+        coroutine::Builder::new().name("TcpServer".to_owned()),
+        move || {
+            loop {
+                let stream = listener.accept();
+                if server.is_shutting_down() {
+                    break;
                 }
+                let stream = t_c!(stream);
+                let conn = server.register(&stream);
+                // t_c!(stream.set_nodelay(true));
+                let info = ConnectionInfo::new(conn.id() as u64, &stream);
+                let service = factory.new_service(&info);
+                let state = ConnState::new(&config, info, proxies.clone());
+                let req_buf = BytesMut::with_capacity(config.request_buf_size);
+                spawn_connection(
+                    stream,
+                    service,
+                    config,
+                    conn,
+                    state,
+                    req_buf,
+                    errors.clone(),
+                );
             }
-        )
-    }
+        }
+    )?;
+    Ok(ServerHandle::new(accept, state, addr))
+}
+
+/// Spawns the accept loop of a listener behind a PROXY protocol load balancer
+///
+/// Each connection reads its PROXY header in a coroutine of its own, so a
+/// slow client can't hold up the others. The connections that got a valid
+/// header in time are handed back to the server coroutine, which owns the
+/// factory, the others are closed.
+fn serve_proxied<F: HttpServiceFactory>(
+    listener: Listener,
+    factory: F,
+    config: HttpConfig,
+    errors: Arc<dyn ErrorHandler>,
+    proxies: Arc<TrustedProxies>,
+    state: Arc<ServerState>,
+    addr: BoundAddr,
+) -> io::Result<ServerHandle> {
+    let server = state.clone();
+    let (tx, rx) = mpsc::channel::<(Stream, ConnGuard, ConnectionInfo, BytesMut)>();
+    go!(
+        coroutine::Builder::new().name("ProxyAccept".to_owned()),
+        move || {
+            loop {
+                let stream = listener.accept();
+                if server.is_shutting_down() {
+                    break;
+                }
+                let stream = t_c!(stream);
+                let conn = server.register(&stream);
+                let tx = tx.clone();
+                go!(move || {
+                    // locals drop in reverse order, `conn` before `stream`
+                    let mut stream = stream;
+                    let conn = conn;
+                    let mut info = ConnectionInfo::new(conn.id() as u64, &stream);
+                    // nothing was received yet, a shutdown can close the connection
+                    if !conn.enter_idle() {
+                        return;
+                    }
+                    // the requests sent along with the header are left in `req_buf`
+                    let mut req_buf = BytesMut::with_capacity(config.request_buf_size);
+                    let deadline = Instant::now() + config.proxy_header_timeout;
+                    let ret = proxy_protocol::read_header(&mut stream, &mut req_buf, deadline);
+                    if !conn.leave_idle() {
+                        return;
+                    }
+                    match ret {
+                        Ok(header) => {
+                            info.set_proxy_header(header);
+                            tx.send((stream, conn, info, req_buf)).ok();
+                        }
+                        Err(e) => {
+                            debug!("dropped connection from {:?}: {e}", info.peer_addr());
+                            stream.shutdown(Shutdown::Both).ok();
+                        }
+                    }
+                });
+            }
+        }
+    )?;
+    // ends once the accept loop and the connections still reading their
+    // header are done
+    let accept = go!(
+        coroutine::Builder::new().name("TcpServer".to_owned()),
+        move || {
+            while let Ok((stream, conn, info, req_buf)) = rx.recv() {
+                let service = factory.new_service(&info);
+                let state = ConnState::new(&config, info, proxies.clone());
+                spawn_connection(
+                    stream,
+                    service,
+                    config,
+                    conn,
+                    state,
+                    req_buf,
+                    errors.clone(),
+                );
+            }
+        }
+    )?;
+    Ok(ServerHandle::new(accept, state, addr))
+}
+
+/// Spawns the coroutine that serves an accepted connection
+///
+/// `req_buf` holds the bytes already received on the connection.
+fn spawn_connection<T: HttpService + Send + 'static>(
+    mut stream: Stream,
+    service: T,
+    config: HttpConfig,
+    conn: ConnGuard,
+    state: ConnState,
+    req_buf: BytesMut,
+    errors: Arc<dyn ErrorHandler>,
+) {
+    let builder = may::coroutine::Builder::new().id(stream.id());
+    go!(builder, move || {
+        let ret = each_connection_loop(
+            &mut stream,
+            service,
+            &config,
+            &conn,
+            state,
+            req_buf,
+            &*errors,
+        );
+        if let Err(e) = ret {
+            // Only log actual errors, not normal client disconnects
+            if !is_client_disconnect(&e) {
+                error!("service err = {e:?}");
+            }
+            stream.shutdown(Shutdown::Both).ok();
+        }
+        // unregister before the socket is closed and its fd reused
+        drop(conn);
+        drop(stream);
+    })
+    .unwrap();
 }
 
 #[inline]
@@ -134,7 +289,7 @@ fn nonblock_write(stream: &mut impl Write, rsp_buf: &mut BytesMut) -> io::Result
     Ok(write_cnt)
 }
 
-const BUF_LEN: usize = 4096 * 8;
+const BUF_LEN: usize = DEFAULT_BUF_SIZE;
 #[inline]
 pub(crate) fn reserve_buf(buf: &mut BytesMut) {
     let rem = buf.capacity() - buf.len();
@@ -160,152 +315,469 @@ pub struct HttpServer<T>(pub T);
 /// ```
 pub struct HttpServerWithHeaders<T, const N: usize>(pub T);
 
-#[cfg(unix)]
-fn each_connection_loop<T: HttpService>(stream: &mut TcpStream, service: T) -> io::Result<()> {
-    each_connection_loop_with_headers::<T, { request::MAX_HEADERS }>(stream, service)
+/// Runs the connection loop with the header array that fits the configured limit
+///
+/// The header storage is a const generic stack array, so the runtime limit is
+/// rounded up to the next supported size and the parser is only handed the
+/// first `max_headers` slots of it.
+fn each_connection_loop<T: HttpService>(
+    stream: &mut Stream,
+    service: T,
+    config: &HttpConfig,
+    conn: &ConnGuard,
+    state: ConnState,
+    req_buf: BytesMut,
+    errors: &dyn ErrorHandler,
+) -> io::Result<()> {
+    match config.max_headers.value() {
+        0..=16 => each_connection_loop_with_headers::<T, 16>(
+            stream, service, config, conn, state, req_buf, errors,
+        ),
+        17..=32 => each_connection_loop_with_headers::<T, 32>(
+            stream, service, config, conn, state, req_buf, errors,
+        ),
+        33..=64 => each_connection_loop_with_headers::<T, 64>(
+            stream, service, config, conn, state, req_buf, errors,
+        ),
+        65..=128 => each_connection_loop_with_headers::<T, 128>(
+            stream, service, config, conn, state, req_buf, errors,
+        ),
+        _ => each_connection_loop_with_headers::<T, 256>(
+            stream, service, config, conn, state, req_buf, errors,
+        ),
+    }
 }
 
 #[cfg(unix)]
 fn each_connection_loop_with_headers<T: HttpService, const N: usize>(
-    stream: &mut TcpStream,
+    stream: &mut Stream,
     mut service: T,
+    config: &HttpConfig,
+    conn: &ConnGuard,
+    conn_state: ConnState,
+    mut req_buf: BytesMut,
+    errors: &dyn ErrorHandler,
 ) -> io::Result<()> {
-    let mut req_buf = BytesMut::with_capacity(BUF_LEN);
-    let mut rsp_buf = BytesMut::with_capacity(BUF_LEN);
+    let header_limit = config.max_headers.value().min(N);
+    let mut rsp_buf = BytesMut::with_capacity(config.response_buf_size);
     let mut body_buf = BytesMut::with_capacity(4096);
+    let timed_wait = config.keep_alive_timeout.is_some() || config.header_read_timeout.is_some();
+    let mut head_started = None;
+    if config.body_read_timeout.is_some() {
+        stream.set_read_timeout(config.body_read_timeout)?;
+    }
 
     loop {
-        let read_blocked = nonblock_read(stream.inner_mut(), &mut req_buf)?;
+        let read_blocked = nonblock_read(&mut stream.raw(), &mut req_buf)?;
 
         // prepare the requests, we should make sure the request is fully read
-        loop {
+        let mut closing = false;
+        while !closing {
             let mut headers = [MaybeUninit::uninit(); N];
-            let req = match request::decode(&mut headers, &mut req_buf, stream)? {
-                Some(req) => req,
-                None => break,
-            };
-            reserve_buf(&mut rsp_buf);
-            let mut rsp = Response::new(&mut body_buf);
-            match service.call(req, &mut rsp) {
-                Ok(()) => response::encode(rsp, &mut rsp_buf),
+            let req = match request::decode(
+                &mut headers[..header_limit],
+                &mut req_buf,
+                stream.shared(),
+                Some(&conn_state),
+            ) {
+                Ok(Some(req)) => req,
+                Ok(None) => break,
                 Err(e) => {
-                    eprintln!("service err = {e:?}");
-                    response::encode_error(e, &mut rsp_buf);
+                    encode_parse_error(e, &mut rsp_buf, &mut body_buf, errors, &conn_state);
+                    closing = true;
+                    break;
                 }
+            };
+            head_started = None;
+            if conn_state.expects_continue() {
+                // `100 Continue` goes straight to the stream, send the earlier responses first
+                stream.shared().write_all(&rsp_buf)?;
+                rsp_buf.clear();
             }
+            reserve_buf(&mut rsp_buf);
+            let mut rsp =
+                Response::with_conn(&mut body_buf, stream.shared(), &mut rsp_buf, &conn_state);
+            let ret = call_service(&mut service, req, &mut rsp, config, &conn_state);
+            request::skip_unread_body(&conn_state, &mut req_buf);
+            closing = encode_response(ret, rsp, &req_buf, conn, &conn_state, errors);
+            conn_state.release_head();
             // here need to use no_delay tcp option
-            // nonblock_write(stream.inner_mut(), &mut rsp_buf)?;
+            // nonblock_write(&mut stream.raw(), &mut rsp_buf)?;
         }
 
         // write out the responses
-        nonblock_write(stream.inner_mut(), &mut rsp_buf)?;
+        nonblock_write(&mut stream.raw(), &mut rsp_buf)?;
+
+        if closing {
+            stream.write_all(&rsp_buf)?;
+            stream.shutdown(Shutdown::Write).ok();
+            return Ok(());
+        }
 
         if read_blocked {
-            stream.wait_io();
+            if timed_wait && !rsp_buf.is_empty() {
+                // a timed wait only watches for reads, flush the responses first
+                stream.write_all(&rsp_buf)?;
+                rsp_buf.clear();
+            }
+            let idle = req_buf.is_empty() && rsp_buf.is_empty();
+            if idle && !conn.enter_idle() {
+                return Ok(());
+            }
+            let received = match read_timeout(config, idle, &mut head_started) {
+                Some(timeout) => timed_read(
+                    stream,
+                    &mut req_buf,
+                    Some(timeout),
+                    config.body_read_timeout,
+                ),
+                None => {
+                    stream.wait_io();
+                    Ok(true)
+                }
+            };
+            if idle && !conn.leave_idle() {
+                return Ok(());
+            }
+            if !received? {
+                return close_timed_out(stream, idle, conn, &mut rsp_buf);
+            }
         }
     }
 }
 
-#[cfg(not(unix))]
-fn each_connection_loop<T: HttpService>(stream: &mut TcpStream, service: T) -> io::Result<()> {
-    each_connection_loop_with_headers::<T, { request::MAX_HEADERS }>(stream, service)
-}
-
 #[cfg(not(unix))]
 fn each_connection_loop_with_headers<T: HttpService, const N: usize>(
-    stream: &mut TcpStream,
+    stream: &mut Stream,
     mut service: T,
+    config: &HttpConfig,
+    conn: &ConnGuard,
+    conn_state: ConnState,
+    mut req_buf: BytesMut,
+    errors: &dyn ErrorHandler,
 ) -> io::Result<()> {
-    let mut req_buf = BytesMut::with_capacity(BUF_LEN);
-    let mut rsp_buf = BytesMut::with_capacity(BUF_LEN);
+    let header_limit = config.max_headers.value().min(N);
+    let mut rsp_buf = BytesMut::with_capacity(config.response_buf_size);
     let mut body_buf = BytesMut::with_capacity(BUF_LEN);
+    let mut head_started = None;
+    stream.set_read_timeout(config.body_read_timeout)?;
+    // bytes received along with the PROXY header may hold whole requests
+    let mut skip_read = !req_buf.is_empty();
     loop {
         // read the socket for requests
-        reserve_buf(&mut req_buf);
-        let read_buf: &mut [u8] = unsafe { std::mem::transmute(&mut *req_buf.chunk_mut()) };
-        let read_cnt = stream.read(read_buf)?;
-        if read_cnt == 0 {
-            //connection was closed
-            return err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+        if !std::mem::take(&mut skip_read) {
+            let idle = req_buf.is_empty();
+            if idle && !conn.enter_idle() {
+                return Ok(());
+            }
+            let timeout = read_timeout(config, idle, &mut head_started);
+            let received = timed_read(stream, &mut req_buf, timeout, config.body_read_timeout);
+            if idle && !conn.leave_idle() {
+                return Ok(());
+            }
+            if !received? {
+                return close_timed_out(stream, idle, conn, &mut rsp_buf);
+            }
         }
-        unsafe { req_buf.advance_mut(read_cnt) };
 
         // prepare the requests
-        if read_cnt > 0 {
-            loop {
-                let mut headers = [MaybeUninit::uninit(); N];
-                let req = match request::decode(&mut headers, &mut req_buf, stream)? {
-                    Some(req) => req,
-                    None => break,
-                };
-                let mut rsp = Response::new(&mut body_buf);
-                match service.call(req, &mut rsp) {
-                    Ok(()) => response::encode(rsp, &mut rsp_buf),
-                    Err(e) => {
-                        eprintln!("service err = {:?}", e);
-                        response::encode_error(e, &mut rsp_buf);
-                    }
+        let mut closing = false;
+        while !closing {
+            let mut headers = [MaybeUninit::uninit(); N];
+            let req = match request::decode(
+                &mut headers[..header_limit],
+                &mut req_buf,
+                stream.shared(),
+                Some(&conn_state),
+            ) {
+                Ok(Some(req)) => req,
+                Ok(None) => break,
+                Err(e) => {
+                    encode_parse_error(e, &mut rsp_buf, &mut body_buf, errors, &conn_state);
+                    closing = true;
+                    break;
                 }
+            };
+            head_started = None;
+            if conn_state.expects_continue() {
+                // `100 Continue` goes straight to the stream, send the earlier responses first
+                stream.shared().write_all(&rsp_buf)?;
+                rsp_buf.clear();
             }
+            let mut rsp =
+                Response::with_conn(&mut body_buf, stream.shared(), &mut rsp_buf, &conn_state);
+            let ret = call_service(&mut service, req, &mut rsp, config, &conn_state);
+            request::skip_unread_body(&conn_state, &mut req_buf);
+            closing = encode_response(ret, rsp, &req_buf, conn, &conn_state, errors);
+            conn_state.release_head();
         }
 
         // send the result back to client
         stream.write_all(&rsp_buf)?;
+        rsp_buf.clear();
+
+        if closing {
+            stream.shutdown(Shutdown::Write).ok();
+            return Ok(());
+        }
+    }
+}
+
+/// Call the service, with [`HttpConfig::catch_panics`] a panic fails the
+/// call and is reported to `conn_state`
+#[inline]
+fn call_service<T: HttpService>(
+    service: &mut T,
+    req: Request,
+    rsp: &mut Response,
+    config: &HttpConfig,
+    conn_state: &ConnState,
+) -> io::Result<()> {
+    if !config.catch_panics {
+        return service.call(req, rsp);
+    }
+    match panic::catch_unwind(AssertUnwindSafe(|| service.call(req, rsp))) {
+        Ok(ret) => ret,
+        Err(payload) => {
+            let msg = match payload.downcast_ref::<&str>() {
+                Some(msg) => msg,
+                None => payload
+                    .downcast_ref::<String>()
+                    .map_or("..", String::as_str),
+            };
+            error!("service panicked on {}: {msg}", conn_state.request_line());
+            conn_state.set_panicked();
+            Err(io::Error::other("service panicked"))
+        }
+    }
+}
+
+/// Encode the outcome of a service call
+///
+/// Returns `true` if the connection must be closed after this response.
+#[inline]
+fn encode_response(
+    mut ret: io::Result<()>,
+    mut rsp: Response,
+    req_buf: &BytesMut,
+    conn: &ConnGuard,
+    conn_state: &ConnState,
+    errors: &dyn ErrorHandler,
+) -> bool {
+    let Some(rsp_buf) = rsp.take_pending() else {
+        unreachable!("served responses are attached to the connection")
+    };
+    conn_state.info().request_served();
+    // the body framing is lost after a failed body read
+    let body_failed = conn_state.take_body_failed();
+    let body_timed_out = conn_state.take_body_timed_out();
+    if body_timed_out {
+        Counters::incr(&conn.counters().body_read_timeouts);
+    }
+    let body_too_large = conn_state.take_body_too_large();
+    if conn_state.take_panicked() && !rsp.is_streaming() {
+        // whatever the service set before it panicked is not sent
+        rsp.reset(StatusCode::INTERNAL_SERVER_ERROR);
+        errors.panic(&mut rsp);
+        ret = Ok(());
+    }
+    // a streamed head was checked before it was sent
+    let ret = ret.and_then(|()| {
+        if rsp.is_streaming() {
+            Ok(())
+        } else {
+            rsp.check_head()
+        }
+    });
+    let closing = body_failed || rsp.closes_connection() || is_last_when_draining(conn, req_buf);
+    match ret {
+        Ok(()) if rsp.is_streaming() => {
+            response::encode_last_chunk(rsp, rsp_buf);
+            closing
+        }
+        Ok(()) => encode_persistent(rsp, closing, rsp_buf),
+        // the head is already out, only closing the connection tells the
+        // client that the body is incomplete
+        Err(e) if rsp.is_streaming() => {
+            error!(
+                "service err while streaming on {}: {e}",
+                conn_state.request_line()
+            );
+            true
+        }
+        Err(_) if body_timed_out => {
+            response::encode_close(StatusCode::REQUEST_TIMEOUT, rsp_buf);
+            true
+        }
+        Err(_) if body_too_large => {
+            response::encode_close(StatusCode::PAYLOAD_TOO_LARGE, rsp_buf);
+            true
+        }
+        Err(e) => {
+            let line = conn_state.request_line();
+            let req = RequestLine::new(&line);
+            let status = error::error_status(&e);
+            if status.is_server_error() {
+                error!("service err on {req}: {e}");
+            } else {
+                debug!("service err on {req}: {e}");
+            }
+            // whatever the service set before it failed is not sent
+            rsp.reset(status);
+            if let Some(message) = HttpError::from_io(&e).and_then(HttpError::message) {
+                rsp.header("Content-Type: text/plain; charset=utf-8");
+                rsp.body_mut().extend_from_slice(message.as_bytes());
+            }
+            errors.service_error(&e, req, &mut rsp);
+            if let Err(err) = rsp.check_head() {
+                error!("error handler response for {req}: {err}");
+                response::encode_close(StatusCode::INTERNAL_SERVER_ERROR, rsp_buf);
+                return true;
+            }
+            encode_persistent(rsp, closing, rsp_buf)
+        }
+    }
+}
+
+/// Encode a response that is not streamed, telling the client whether the
+/// connection stays open
+#[inline]
+fn encode_persistent(mut rsp: Response, closing: bool, rsp_buf: &mut BytesMut) -> bool {
+    if closing {
+        rsp.headers_mut().insert("Connection", "close");
+    } else if rsp.is_http10() {
+        // HTTP/1.0 connections only persist when both ends say so
+        rsp.headers_mut().insert("Connection", "keep-alive");
+    }
+    response::encode(rsp, rsp_buf);
+    closing
+}
+
+/// Answer a request that could not be decoded, the connection is closed
+/// after the response
+#[cold]
+fn encode_parse_error(
+    e: ParseError,
+    rsp_buf: &mut BytesMut,
+    body_buf: &mut BytesMut,
+    errors: &dyn ErrorHandler,
+    conn_state: &ConnState,
+) {
+    debug!("{e}");
+    let mut rsp = Response::new(body_buf);
+    rsp.set_check_head(conn_state.check_headers());
+    rsp.status(e.status());
+    errors.parse_error(&e, &mut rsp);
+    if let Err(err) = rsp.check_head() {
+        error!("error handler response for {e}: {err}");
+        return response::encode_close(e.status(), rsp_buf);
+    }
+    rsp.headers_mut().insert("Connection", "close");
+    response::encode(rsp, rsp_buf);
+}
+
+/// While the server is draining, the response to the last complete request
+/// already received is the last one sent on the connection
+#[inline]
+fn is_last_when_draining(conn: &ConnGuard, req_buf: &BytesMut) -> bool {
+    conn.is_draining() && !request::is_head_complete(req_buf)
+}
+
+/// How long to wait for more request bytes, `None` waits without a timeout
+///
+/// An idle connection waits for the keep-alive timeout, a partially received
+/// request head for what is left of the header read timeout.
+#[inline]
+fn read_timeout(
+    config: &HttpConfig,
+    idle: bool,
+    head_started: &mut Option<Instant>,
+) -> Option<Duration> {
+    if idle {
+        return config.keep_alive_timeout;
+    }
+    let limit = config.header_read_timeout?;
+    let started = *head_started.get_or_insert_with(Instant::now);
+    Some(limit.saturating_sub(started.elapsed()))
+}
+
+/// Blocking read of more request bytes
+///
+/// Returns `false` if nothing arrived within `timeout`. The stream read
+/// timeout is restored to `body_timeout` for the service afterwards.
+fn timed_read(
+    stream: &mut Stream,
+    req_buf: &mut BytesMut,
+    timeout: Option<Duration>,
+    body_timeout: Option<Duration>,
+) -> io::Result<bool> {
+    if timeout.is_some_and(|t| t.is_zero()) {
+        return Ok(false);
+    }
+    let switch = timeout != body_timeout;
+    if switch {
+        stream.set_read_timeout(timeout)?;
+    }
+    reserve_buf(req_buf);
+    let read_buf: &mut [u8] = unsafe { std::mem::transmute(&mut *req_buf.chunk_mut()) };
+    let ret = stream.read(read_buf);
+    if switch {
+        stream.set_read_timeout(body_timeout)?;
+    }
+    match ret {
+        Ok(0) => err(io::Error::new(io::ErrorKind::BrokenPipe, "read closed")),
+        Ok(n) => {
+            unsafe { req_buf.advance_mut(n) };
+            Ok(true)
+        }
+        Err(e) if is_timeout(&e) => Ok(false),
+        Err(e) => err(e),
+    }
+}
+
+/// Close a connection that did not deliver the next request in time
+#[cold]
+fn close_timed_out(
+    stream: &mut Stream,
+    idle: bool,
+    conn: &ConnGuard,
+    rsp_buf: &mut BytesMut,
+) -> io::Result<()> {
+    if idle {
+        Counters::incr(&conn.counters().keep_alive_timeouts);
+    } else {
+        Counters::incr(&conn.counters().header_read_timeouts);
+        response::encode_close(StatusCode::REQUEST_TIMEOUT, rsp_buf);
+        stream.write_all(rsp_buf)?;
+    }
+    stream.shutdown(Shutdown::Write).ok();
+    Ok(())
+}
+
+impl<T: HttpService + Clone + Send + Sync + 'static> HttpServiceFactory for HttpServer<T> {
+    type Service = T;
+
+    fn new_service(&self, _conn: &ConnectionInfo) -> T {
+        self.0.clone()
     }
 }
 
 impl<T: HttpService + Clone + Send + Sync + 'static> HttpServer<T> {
     /// Spawns the http service, binding to the given address
-    /// return a coroutine that you can cancel it when need to stop the service
-    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
-        let listener = TcpListener::bind(addr)?;
-        let service = self.0;
-        go!(
-            coroutine::Builder::new().name("TcpServer".to_owned()),
-            move || {
-                for stream in listener.incoming() {
-                    let mut stream = t_c!(stream);
-                    // t_c!(stream.set_nodelay(true));
-                    let service = service.clone();
-                    go!(
-                        move || if let Err(e) = each_connection_loop(&mut stream, service) {
-                            // Only log actual errors, not normal client disconnects
-                            if !is_client_disconnect(&e) {
-                                error!("service err = {e:?}");
-                            }
-                            stream.shutdown(std::net::Shutdown::Both).ok();
-                        }
-                    );
-                }
-            }
-        )
+    /// return a [`ServerHandle`] that you can use to stop the service
+    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
+        ServerBuilder::new(self).bind(addr)
     }
 }
 
 impl<T: HttpService + Clone + Send + Sync + 'static, const N: usize> HttpServerWithHeaders<T, N> {
     /// Spawns the http service with custom max headers, binding to the given address
-    /// return a coroutine that you can cancel it when need to stop the service
-    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
-        let listener = TcpListener::bind(addr)?;
-        let service = self.0;
-        go!(
-            coroutine::Builder::new().name("TcpServer".to_owned()),
-            move || {
-                for stream in listener.incoming() {
-                    let mut stream = t_c!(stream);
-                    // t_c!(stream.set_nodelay(true));
-                    let service = service.clone();
-                    go!(move || if let Err(e) =
-                        each_connection_loop_with_headers::<T, N>(&mut stream, service)
-                    {
-                        // Only log actual errors, not normal client disconnects
-                        if !is_client_disconnect(&e) {
-                            error!("service err = {e:?}");
-                        }
-                        stream.shutdown(std::net::Shutdown::Both).ok();
-                    });
-                }
-            }
-        )
+    /// return a [`ServerHandle`] that you can use to stop the service
+    ///
+    /// `N` is applied as [`MaxHeaders::Custom`], so it is clamped to 256.
+    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
+        ServerBuilder::new(HttpServer(self.0))
+            .max_headers(MaxHeaders::Custom(N))
+            .bind(addr)
     }
 }
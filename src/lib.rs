@@ -1,13 +1,44 @@
 #[macro_use]
 extern crate log;
 
+mod config;
+mod connection;
 mod date;
+mod error;
+mod forwarded;
+mod headers;
 mod http_server;
+mod listener;
+mod proxy_protocol;
 mod request;
 mod response;
+mod response_headers;
+mod router;
+mod server_builder;
+mod server_handle;
+mod status;
+mod uri;
 
+pub use config::HttpConfig;
+pub use connection::ConnectionInfo;
+pub use error::{ErrorHandler, HttpError, ParseError, RequestLine};
+pub use forwarded::{ForwardedHeaders, InvalidIpNet, IpNet};
+pub use headers::{
+    Authorization, ByteRange, Connection, Cookies, EntityTag, HeaderError, Host, IfNoneMatch,
+    MediaType,
+};
 pub use http_server::{HttpServer, HttpServerWithHeaders, HttpService, HttpServiceFactory};
+#[cfg(unix)]
+pub use listener::UnixSocket;
+pub use proxy_protocol::ProxyHeader;
 pub use request::{
-    decode_default, decode_large, decode_standard, decode_xlarge, BodyReader, MaxHeaders, Request,
+    decode_default, decode_large, decode_standard, decode_xlarge, BodyError, BodyReader,
+    MaxHeaders, Request,
 };
-pub use response::{IntoResponseHeader, Response, ResponseHeader};
+pub use response::{ChunkedWriter, IntoResponseHeader, Response, ResponseHeader};
+pub use response_headers::ResponseHeaders;
+pub use router::{Params, Router};
+pub use server_builder::ServerBuilder;
+pub use server_handle::{ServerHandle, ServerStats};
+pub use status::{InvalidStatusCode, StatusCode};
+pub use uri::QueryPairs;
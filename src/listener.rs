@@ -0,0 +1,446 @@
+//! the sockets the server listens on and the connections it accepts
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::time::Duration;
+
+use may::net::{TcpListener, TcpStream};
+
+#[cfg(unix)]
+pub use self::unix::UnixSocket;
+
+/// A bound listening socket
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(may::os::unix::net::UnixListener, UnixSocket),
+}
+
+/// What a server listens on, the [`ServerHandle`](crate::ServerHandle)
+/// connects to it to wake up the accept loop
+pub(crate) enum BoundAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+impl Listener {
+    pub(crate) fn bound_addr(&self) -> io::Result<BoundAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(BoundAddr::Tcp),
+            #[cfg(unix)]
+            Listener::Unix(_, socket) => Ok(BoundAddr::Unix(socket.clone())),
+        }
+    }
+
+    /// Wait for the next connection
+    pub(crate) fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(s, _)| Stream::Tcp(s)),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener.accept().map(|(s, _)| Stream::unix(s)),
+        }
+    }
+}
+
+/// An accepted connection
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix {
+        io: may::os::unix::net::UnixStream,
+        // the same socket without the coroutine scheduling, never closed
+        raw: std::mem::ManuallyDrop<std::os::unix::net::UnixStream>,
+    },
+}
+
+/// A shared reference to an accepted connection, what requests read their
+/// body from and responses are streamed to
+#[derive(Clone, Copy)]
+pub(crate) enum StreamRef<'a> {
+    Tcp(&'a TcpStream),
+    #[cfg(unix)]
+    Unix(&'a may::os::unix::net::UnixStream),
+}
+
+impl Stream {
+    #[cfg(unix)]
+    fn unix(io: may::os::unix::net::UnixStream) -> Self {
+        use std::os::fd::{AsRawFd, FromRawFd};
+        // safety: the fd stays open as long as `io`, and `raw` never closes it
+        let raw = unsafe { std::os::unix::net::UnixStream::from_raw_fd(io.as_raw_fd()) };
+        Stream::Unix {
+            io,
+            raw: std::mem::ManuallyDrop::new(raw),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn shared(&self) -> StreamRef<'_> {
+        match self {
+            Stream::Tcp(s) => StreamRef::Tcp(s),
+            #[cfg(unix)]
+            Stream::Unix { io, .. } => StreamRef::Unix(io),
+        }
+    }
+
+    /// Whether the connection was accepted on a Unix socket
+    pub(crate) fn is_unix(&self) -> bool {
+        match self {
+            Stream::Tcp(_) => false,
+            #[cfg(unix)]
+            Stream::Unix { .. } => true,
+        }
+    }
+
+    /// The address of the client, `None` for a Unix socket
+    pub(crate) fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Stream::Tcp(s) => s.peer_addr().ok(),
+            #[cfg(unix)]
+            Stream::Unix { .. } => None,
+        }
+    }
+
+    /// The address the client connected to, `None` for a Unix socket
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Stream::Tcp(s) => s.local_addr().ok(),
+            #[cfg(unix)]
+            Stream::Unix { .. } => None,
+        }
+    }
+
+    /// The number of the socket, the connection coroutine is named after it
+    pub(crate) fn id(&self) -> usize {
+        #[cfg(unix)]
+        use std::os::fd::AsRawFd;
+        #[cfg(windows)]
+        use std::os::windows::io::AsRawSocket;
+        match self {
+            #[cfg(unix)]
+            Stream::Tcp(s) => s.as_raw_fd() as usize,
+            #[cfg(windows)]
+            Stream::Tcp(s) => s.as_raw_socket() as usize,
+            #[cfg(unix)]
+            Stream::Unix { io, .. } => io.as_raw_fd() as usize,
+        }
+    }
+
+    pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix { io, .. } => io.set_read_timeout(timeout),
+        }
+    }
+
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix { io, .. } => io.shutdown(how),
+        }
+    }
+
+    /// Park the coroutine until the socket is readable
+    #[cfg(unix)]
+    #[inline]
+    pub(crate) fn wait_io(&self) {
+        use may::io::WaitIo;
+        match self {
+            Stream::Tcp(s) => s.wait_io(),
+            Stream::Unix { io, .. } => io.wait_io(),
+        }
+    }
+
+    /// The socket without the coroutine scheduling, reads and writes that
+    /// would block return `WouldBlock`
+    #[cfg(unix)]
+    #[inline]
+    pub(crate) fn raw(&mut self) -> RawStream<'_> {
+        match self {
+            Stream::Tcp(s) => RawStream::Tcp(s.inner_mut()),
+            Stream::Unix { raw, .. } => RawStream::Unix(raw),
+        }
+    }
+}
+
+impl Read for Stream {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.shared().read(buf)
+    }
+}
+
+impl Write for Stream {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.shared().write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.shared().flush()
+    }
+}
+
+impl Read for StreamRef<'_> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            StreamRef::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            StreamRef::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for StreamRef<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            StreamRef::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            StreamRef::Unix(s) => s.write(buf),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            StreamRef::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            StreamRef::Unix(s) => s.flush(),
+        }
+    }
+}
+
+/// See [`Stream::raw`]
+#[cfg(unix)]
+pub(crate) enum RawStream<'a> {
+    Tcp(&'a mut std::net::TcpStream),
+    Unix(&'a std::os::unix::net::UnixStream),
+}
+
+#[cfg(unix)]
+impl Read for RawStream<'_> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            RawStream::Tcp(s) => s.read(buf),
+            RawStream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Write for RawStream<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            RawStream::Tcp(s) => s.write(buf),
+            RawStream::Unix(s) => s.write(buf),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::OsString;
+    use std::fs;
+    use std::io;
+    use std::os::fd::{FromRawFd, IntoRawFd};
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::os::unix::net as std_net;
+    use std::path::{Path, PathBuf};
+
+    use may::os::unix::net::UnixListener;
+
+    /// A Unix domain socket to listen on, see
+    /// [`ServerBuilder::bind_unix`](crate::ServerBuilder::bind_unix)
+    ///
+    /// Connections over a Unix socket have no
+    /// [`peer_addr`](crate::ConnectionInfo::peer_addr); who may connect is
+    /// decided by the permissions of the socket file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, UnixSocket};
+    /// use std::io;
+    ///
+    /// #[derive(Clone)]
+    /// struct Hello;
+    ///
+    /// impl HttpService for Hello {
+    ///     fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+    ///         rsp.body("Hello, world!");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let socket = UnixSocket::path("/run/hello/http.sock").mode(0o660);
+    /// let server = ServerBuilder::new(HttpServer(Hello))
+    ///     .bind_unix(socket)
+    ///     .unwrap();
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct UnixSocket {
+        name: Name,
+        mode: Option<u32>,
+        remove_stale: bool,
+    }
+
+    #[derive(Debug, Clone)]
+    enum Name {
+        Path(PathBuf),
+        #[cfg(target_os = "linux")]
+        Abstract(Vec<u8>),
+    }
+
+    impl UnixSocket {
+        /// A socket file at `path`
+        pub fn path(path: impl Into<PathBuf>) -> Self {
+            UnixSocket {
+                name: Name::Path(path.into()),
+                mode: None,
+                remove_stale: true,
+            }
+        }
+
+        /// A socket in the abstract namespace, `name` is without the leading
+        /// NUL byte
+        ///
+        /// The socket has no file, it goes away with the server and any
+        /// process in the same network namespace can connect.
+        #[cfg(target_os = "linux")]
+        pub fn abstract_name(name: impl Into<Vec<u8>>) -> Self {
+            UnixSocket {
+                name: Name::Abstract(name.into()),
+                mode: None,
+                remove_stale: false,
+            }
+        }
+
+        /// Set the permission bits of the socket file, like `0o660`
+        ///
+        /// The file has them before any client can connect. Without it the
+        /// file gets the default permissions of the process umask. Has no
+        /// effect on an abstract socket.
+        pub fn mode(mut self, mode: u32) -> Self {
+            self.mode = Some(mode);
+            self
+        }
+
+        /// Remove a socket file left behind by a server that did not shut
+        /// down, on by default
+        ///
+        /// A file some server still accepts connections on is kept and
+        /// binding fails with `AddrInUse`, as it does for a file that is not
+        /// a socket.
+        pub fn remove_stale(mut self, remove: bool) -> Self {
+            self.remove_stale = remove;
+            self
+        }
+
+        /// The path of the socket file, `None` for an abstract socket
+        pub fn as_path(&self) -> Option<&Path> {
+            match &self.name {
+                Name::Path(path) => Some(path),
+                #[cfg(target_os = "linux")]
+                Name::Abstract(_) => None,
+            }
+        }
+
+        pub(crate) fn bind(&self) -> io::Result<UnixListener> {
+            let listener = match &self.name {
+                Name::Path(path) => {
+                    if self.remove_stale {
+                        remove_stale(path)?;
+                    }
+                    match self.mode {
+                        Some(mode) => bind_with_mode(path, mode)?,
+                        None => std_net::UnixListener::bind(path)?,
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                Name::Abstract(name) => {
+                    use std::os::linux::net::SocketAddrExt;
+                    let addr = std_net::SocketAddr::from_abstract_name(name)?;
+                    std_net::UnixListener::bind_addr(&addr)?
+                }
+            };
+            // the coroutine runtime takes over the socket
+            Ok(unsafe { UnixListener::from_raw_fd(listener.into_raw_fd()) })
+        }
+
+        /// Connect without the coroutine runtime, to wake up the accept loop
+        pub(crate) fn connect(&self) -> io::Result<std_net::UnixStream> {
+            match &self.name {
+                Name::Path(path) => std_net::UnixStream::connect(path),
+                #[cfg(target_os = "linux")]
+                Name::Abstract(name) => {
+                    use std::os::linux::net::SocketAddrExt;
+                    std_net::UnixStream::connect_addr(&std_net::SocketAddr::from_abstract_name(
+                        name,
+                    )?)
+                }
+            }
+        }
+
+        /// Remove the socket file once the server stopped listening
+        pub(crate) fn unlink(&self) {
+            if let Some(path) = self.as_path() {
+                fs::remove_file(path).ok();
+            }
+        }
+    }
+
+    /// Bind a socket file that has the permission bits `mode` before anyone
+    /// can connect to it
+    ///
+    /// The socket is bound in a private directory next to `path`, gets its
+    /// permissions there and is then linked into place. Unlike a rename, the
+    /// link fails instead of replacing a file that is already at `path`.
+    fn bind_with_mode(path: &Path, mode: u32) -> io::Result<std_net::UnixListener> {
+        let Some(name) = path.file_name() else {
+            return std_net::UnixListener::bind(path);
+        };
+        let mut dir_name = OsString::from(".");
+        dir_name.push(name);
+        dir_name.push(format!(".{}.bind", std::process::id()));
+        let dir = path.with_file_name(dir_name);
+        fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        let staged = dir.join("socket");
+        let ret = std_net::UnixListener::bind(&staged).and_then(|listener| {
+            fs::set_permissions(&staged, fs::Permissions::from_mode(mode))?;
+            fs::hard_link(&staged, path).map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io::ErrorKind::AddrInUse.into(),
+                _ => e,
+            })?;
+            Ok(listener)
+        });
+        fs::remove_file(&staged).ok();
+        fs::remove_dir(&dir).ok();
+        ret
+    }
+
+    /// Remove the socket file at `path` if nobody accepts connections on it
+    fn remove_stale(path: &Path) -> io::Result<()> {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => {}
+            // nothing there, or binding fails on a file that is not a socket
+            _ => return Ok(()),
+        }
+        match std_net::UnixStream::connect(path) {
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! the PROXY protocol header a load balancer sends ahead of the proxied bytes
+
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Instant;
+
+use bytes::{BufMut, BytesMut};
+
+use crate::http_server::{is_timeout, reserve_buf};
+use crate::listener::Stream;
+
+// the v2 binary header starts with this signature
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+// the fixed part of a v2 header, the signature, version and command,
+// address family and the length of the rest
+const V2_HEADER_LEN: usize = 16;
+// the v1 text header is at most 107 bytes including the CRLF
+const V1_MAX_LEN: usize = 107;
+// the shortest header, `PROXY UNKNOWN\r\n`
+const MIN_LEN: usize = 15;
+
+/// The PROXY protocol header that opened a connection
+///
+/// With [`HttpConfig::proxy_protocol`](crate::HttpConfig::proxy_protocol)
+/// every connection has to start with a version 1 or version 2 header, the
+/// one of the connection is available from
+/// [`ConnectionInfo::proxy_header`](crate::ConnectionInfo::proxy_header).
+/// The original addresses also replace the ones of the load balancer in
+/// [`ConnectionInfo::peer_addr`](crate::ConnectionInfo::peer_addr) and
+/// [`ConnectionInfo::local_addr`](crate::ConnectionInfo::local_addr).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyHeader {
+    version: u8,
+    source: Option<SocketAddr>,
+    destination: Option<SocketAddr>,
+    // the type-length-value fields of a v2 header, checked to be well formed
+    tlvs: Vec<u8>,
+}
+
+impl ProxyHeader {
+    /// `PP2_TYPE_ALPN`, the protocol the client negotiated
+    pub const TLV_ALPN: u8 = 0x01;
+    /// `PP2_TYPE_AUTHORITY`, the host name the client asked for with SNI
+    pub const TLV_AUTHORITY: u8 = 0x02;
+    /// `PP2_TYPE_CRC32C`, the checksum of the header
+    pub const TLV_CRC32C: u8 = 0x03;
+    /// `PP2_TYPE_UNIQUE_ID`, an id the load balancer gave the connection
+    pub const TLV_UNIQUE_ID: u8 = 0x05;
+    /// `PP2_TYPE_SSL`, details of the TLS connection to the load balancer
+    pub const TLV_SSL: u8 = 0x20;
+    /// `PP2_TYPE_NETNS`, the network namespace the connection was accepted in
+    pub const TLV_NETNS: u8 = 0x30;
+
+    /// The protocol version of the header, `1` or `2`
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The address of the original client
+    ///
+    /// `None` for a v1 `UNKNOWN` or a v2 `LOCAL` connection, such as a health
+    /// check of the load balancer, and for addresses other than IPv4 and IPv6.
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
+
+    /// The address the original client connected to, `None` like
+    /// [`source`](Self::source)
+    pub fn destination(&self) -> Option<SocketAddr> {
+        self.destination
+    }
+
+    /// The value of the first type-length-value field of type `kind`
+    pub fn tlv(&self, kind: u8) -> Option<&[u8]> {
+        self.tlvs()
+            .find_map(|(k, value)| (k == kind).then_some(value))
+    }
+
+    /// The `(type, value)` fields of a v2 header in the order they were sent
+    pub fn tlvs(&self) -> impl Iterator<Item = (u8, &[u8])> {
+        let mut rest = &self.tlvs[..];
+        std::iter::from_fn(move || {
+            let (kind, value, tail) = split_tlv(rest)?;
+            rest = tail;
+            Some((kind, value))
+        })
+    }
+}
+
+/// Split the first type-length-value field off `buf`
+#[inline]
+fn split_tlv(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let [kind, hi, lo, rest @ ..] = buf else {
+        return None;
+    };
+    let len = u16::from_be_bytes([*hi, *lo]) as usize;
+    (len <= rest.len()).then(|| (*kind, &rest[..len], &rest[len..]))
+}
+
+#[cold]
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read the PROXY header the connection starts with
+///
+/// The header is read into `buf` and split off it, the HTTP bytes received
+/// along with it are left in `buf` for the connection loop. Fails with
+/// `InvalidData` if the connection does not start with a valid header and
+/// with `TimedOut` if the header is not complete by `deadline`.
+pub(crate) fn read_header(
+    stream: &mut Stream,
+    buf: &mut BytesMut,
+    deadline: Instant,
+) -> io::Result<ProxyHeader> {
+    read_min(stream, buf, MIN_LEN, deadline)?;
+    let ret = if buf.starts_with(V2_SIGNATURE) {
+        read_min(stream, buf, V2_HEADER_LEN, deadline)?;
+        let len = V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+        read_min(stream, buf, len, deadline)?;
+        parse_v2(&buf.split_to(len))
+    } else if buf.starts_with(b"PROXY ") {
+        // the end of the line is only known once it is read
+        let len = loop {
+            let line = &buf[..buf.len().min(V1_MAX_LEN)];
+            if let Some(pos) = line.windows(2).position(|w| w == b"\r\n") {
+                break pos + 2;
+            }
+            if line.len() == V1_MAX_LEN {
+                return Err(invalid("PROXY v1 header too long"));
+            }
+            read_min(stream, buf, buf.len() + 1, deadline)?;
+        };
+        parse_v1(&buf.split_to(len))
+    } else {
+        Err(invalid("connection does not start with a PROXY header"))
+    };
+    stream.set_read_timeout(None)?;
+    ret
+}
+
+/// Read until `buf` holds at least `len` bytes, failing once `deadline`
+/// passed
+fn read_min(
+    stream: &mut Stream,
+    buf: &mut BytesMut,
+    len: usize,
+    deadline: Instant,
+) -> io::Result<()> {
+    let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "PROXY header timed out");
+    while buf.len() < len {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(timed_out());
+        }
+        stream.set_read_timeout(Some(left))?;
+        reserve_buf(buf);
+        let read_buf: &mut [u8] = unsafe { std::mem::transmute(buf.chunk_mut()) };
+        match stream.read(read_buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => unsafe { buf.advance_mut(n) },
+            Err(e) if is_timeout(&e) => return Err(timed_out()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Parse a v1 header, `buf` is the whole line including the CRLF
+fn parse_v1(buf: &[u8]) -> io::Result<ProxyHeader> {
+    let line = std::str::from_utf8(&buf[..buf.len() - 2])
+        .map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    let mut fields = line.split(' ').skip(1);
+    let (source, destination) = match fields.next() {
+        // the rest of the line is to be ignored
+        Some("UNKNOWN") => (None, None),
+        Some(family @ ("TCP4" | "TCP6")) => {
+            let mut next = || {
+                fields
+                    .next()
+                    .ok_or_else(|| invalid("PROXY v1 header is truncated"))
+            };
+            let (src, dst) = (next()?, next()?);
+            let (src_port, dst_port) = (next()?, next()?);
+            if fields.next().is_some() {
+                return Err(invalid("PROXY v1 header has extra fields"));
+            }
+            let v6 = family == "TCP6";
+            (
+                Some(v1_addr(src, src_port, v6)?),
+                Some(v1_addr(dst, dst_port, v6)?),
+            )
+        }
+        _ => return Err(invalid("PROXY v1 header has an unknown protocol")),
+    };
+    Ok(ProxyHeader {
+        version: 1,
+        source,
+        destination,
+        tlvs: Vec::new(),
+    })
+}
+
+/// A v1 address and port, the address has to be of the announced family
+fn v1_addr(ip: &str, port: &str, v6: bool) -> io::Result<SocketAddr> {
+    let ip = match v6 {
+        false => ip.parse::<Ipv4Addr>().map(IpAddr::V4),
+        true => ip.parse::<Ipv6Addr>().map(IpAddr::V6),
+    };
+    let ip = ip.map_err(|_| invalid("PROXY v1 header has an invalid address"))?;
+    // `u16::from_str` takes a leading `+`, the protocol only has digits
+    let port = match port.bytes().all(|b| b.is_ascii_digit()) {
+        true => port.parse::<u16>().ok(),
+        false => None,
+    };
+    let port = port.ok_or_else(|| invalid("PROXY v1 header has an invalid port"))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Parse a v2 header, `buf` is the whole header
+fn parse_v2(buf: &[u8]) -> io::Result<ProxyHeader> {
+    let (version, command) = (buf[12] >> 4, buf[12] & 0x0f);
+    if version != 2 {
+        return Err(invalid("PROXY v2 header has an unknown version"));
+    }
+    let (family, transport) = (buf[13] >> 4, buf[13] & 0x0f);
+    // only stream connections are proxied to an http server
+    if transport > 1 {
+        return Err(invalid("PROXY v2 header is not for a stream connection"));
+    }
+    let rest = &buf[V2_HEADER_LEN..];
+    let addr_len = match family {
+        0 => 0,
+        1 => 12,
+        2 => 36,
+        3 => 216,
+        _ => return Err(invalid("PROXY v2 header has an unknown address family")),
+    };
+    if rest.len() < addr_len {
+        return Err(invalid("PROXY v2 header is too short for its addresses"));
+    }
+    let (addrs, tlvs) = rest.split_at(addr_len);
+    let mut check = tlvs;
+    while !check.is_empty() {
+        let (_, _, tail) =
+            split_tlv(check).ok_or_else(|| invalid("PROXY v2 header has a broken TLV"))?;
+        check = tail;
+    }
+    let (source, destination) = match (command, family) {
+        // `LOCAL`, the load balancer speaks for itself and the addresses are
+        // to be ignored
+        (0, _) => (None, None),
+        (1, 1) => {
+            let ip = |at: usize| Ipv4Addr::from(<[u8; 4]>::try_from(&addrs[at..at + 4]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([addrs[at], addrs[at + 1]]);
+            (
+                Some(SocketAddr::new(ip(0).into(), port(8))),
+                Some(SocketAddr::new(ip(4).into(), port(10))),
+            )
+        }
+        (1, 2) => {
+            let ip = |at: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&addrs[at..at + 16]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([addrs[at], addrs[at + 1]]);
+            (
+                Some(SocketAddr::new(ip(0).into(), port(32))),
+                Some(SocketAddr::new(ip(16).into(), port(34))),
+            )
+        }
+        // unspecified or unix socket addresses
+        (1, _) => (None, None),
+        _ => return Err(invalid("PROXY v2 header has an unknown command")),
+    };
+    Ok(ProxyHeader {
+        version: 2,
+        source,
+        destination,
+        tlvs: tlvs.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A v2 header for a stream connection of `family`
+    fn v2(command: u8, family: u8, addrs: &[u8], tlvs: &[u8]) -> Vec<u8> {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.push(0x20 | command);
+        buf.push(family << 4 | 1);
+        buf.extend_from_slice(&((addrs.len() + tlvs.len()) as u16).to_be_bytes());
+        buf.extend_from_slice(addrs);
+        buf.extend_from_slice(tlvs);
+        buf
+    }
+
+    const INET: [u8; 12] = [203, 0, 113, 7, 198, 51, 100, 1, 0x10, 0x92, 0x01, 0xbb];
+
+    #[test]
+    fn v1_addresses() {
+        let header = parse_v1(b"PROXY TCP4 203.0.113.7 198.51.100.1 4242 443\r\n").unwrap();
+        assert_eq!(header.version(), 1);
+        assert_eq!(header.source(), Some("203.0.113.7:4242".parse().unwrap()));
+        assert_eq!(
+            header.destination(),
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+
+        let header = parse_v1(b"PROXY TCP6 2001:db8::7 2001:db8::1 4242 443\r\n").unwrap();
+        assert_eq!(header.source(), Some("[2001:db8::7]:4242".parse().unwrap()));
+
+        // the rest of an `UNKNOWN` line is ignored
+        let header = parse_v1(b"PROXY UNKNOWN whatever\r\n").unwrap();
+        assert_eq!((header.source(), header.destination()), (None, None));
+    }
+
+    #[test]
+    fn invalid_v1_headers() {
+        for line in [
+            &b"PROXY UDP4 203.0.113.7 198.51.100.1 4242 443\r\n"[..],
+            b"PROXY TCP4 203.0.113.7 198.51.100.1 4242\r\n",
+            b"PROXY TCP4 203.0.113.7 198.51.100.1 4242 443 1\r\n",
+            b"PROXY TCP4 2001:db8::7 2001:db8::1 4242 443\r\n",
+            b"PROXY TCP6 203.0.113.7 198.51.100.1 4242 443\r\n",
+            b"PROXY TCP4 203.0.113.7 198.51.100.1 +4242 443\r\n",
+            b"PROXY TCP4 203.0.113.7 198.51.100.1 4242 65536\r\n",
+        ] {
+            assert!(parse_v1(line).is_err(), "{}", String::from_utf8_lossy(line));
+        }
+    }
+
+    #[test]
+    fn v2_addresses_and_tlvs() {
+        let tlvs = b"\x04\x00\x02\0\0\x02\x00\x0bexample.com";
+        let header = parse_v2(&v2(1, 1, &INET, tlvs)).unwrap();
+        assert_eq!(header.version(), 2);
+        assert_eq!(header.source(), Some("203.0.113.7:4242".parse().unwrap()));
+        assert_eq!(
+            header.destination(),
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+        assert_eq!(
+            header.tlv(ProxyHeader::TLV_AUTHORITY),
+            Some(&b"example.com"[..])
+        );
+        assert_eq!(
+            header.tlvs().map(|(kind, _)| kind).collect::<Vec<_>>(),
+            [4, 2]
+        );
+
+        let mut addrs = [0u8; 36];
+        addrs[..2].copy_from_slice(&[0x20, 0x01]);
+        addrs[16..18].copy_from_slice(&[0x20, 0x01]);
+        addrs[32..].copy_from_slice(&[0x10, 0x92, 0x01, 0xbb]);
+        let header = parse_v2(&v2(1, 2, &addrs, b"")).unwrap();
+        assert_eq!(header.source(), Some("[2001::]:4242".parse().unwrap()));
+        assert_eq!(header.destination(), Some("[2001::]:443".parse().unwrap()));
+    }
+
+    /// `LOCAL` and unix socket headers carry no usable addresses
+    #[test]
+    fn v2_without_addresses() {
+        let header = parse_v2(&v2(0, 1, &INET, b"")).unwrap();
+        assert_eq!((header.source(), header.destination()), (None, None));
+        let header = parse_v2(&v2(1, 3, &[0; 216], b"")).unwrap();
+        assert_eq!((header.source(), header.destination()), (None, None));
+    }
+
+    #[test]
+    fn invalid_v2_headers() {
+        let mut version = v2(1, 1, &INET, b"");
+        version[12] = 0x11;
+        let mut datagram = v2(1, 1, &INET, b"");
+        datagram[13] = 0x12;
+        for header in [
+            version,
+            datagram,
+            v2(2, 1, &INET, b""),
+            v2(1, 4, &INET, b""),
+            v2(1, 1, &INET[..4], b""),
+            v2(1, 1, &INET, b"\x02\x00\x09host"),
+        ] {
+            assert!(parse_v2(&header).is_err(), "{header:?}");
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use std::borrow::Cow;
+use std::error::Error;
 use std::fmt;
-use std::io::{self, BufRead, Read};
+use std::io::{self, BufRead, Read, Write};
 use std::mem::MaybeUninit;
+use std::net::IpAddr;
 
 /// Maximum header buffer size configurations.
 ///
@@ -156,87 +159,358 @@ impl MaxHeaders {
     }
 }
 
-/// Default maximum number of HTTP headers (backwards compatible)
-pub(crate) const MAX_HEADERS: usize = MaxHeaders::Default.value();
-
 use bytes::{Buf, BufMut, BytesMut};
 use may::net::TcpStream;
 
-use crate::http_server::err;
+use crate::connection::{ConnState, ConnectionInfo};
+use crate::error::ParseError;
+use crate::forwarded::{self, Forwarded};
+use crate::headers::{
+    self, Authorization, ByteRange, Connection, Cookies, EntityTag, HeaderError, Host, IfNoneMatch,
+    ListItems, MediaType,
+};
+use crate::http_server::{err, is_timeout};
+use crate::listener::StreamRef;
+use crate::uri::{self, QueryPairs};
 
 pub struct BodyReader<'buf, 'stream> {
     // remaining bytes for body
     req_buf: &'buf mut BytesMut,
-    // the max body length limit
-    body_limit: usize,
-    // total read count
+    // how the end of the body is found
+    framing: Framing,
+    // the most body bytes the service may read
+    limit: usize,
+    // body bytes read so far
     total_read: usize,
-    // used to read extra body bytes
-    stream: &'stream mut TcpStream,
+    // chunk framing bytes consumed so far, size lines, CRLFs and trailers
+    framing_read: usize,
+    // used to read extra body bytes, shared with the response that may
+    // stream its body while the request is alive
+    stream: StreamRef<'stream>,
+    // state of the serving connection, `None` for a standalone decode
+    conn: Option<&'stream ConnState>,
+}
+
+/// Framing of a request body
+#[derive(Clone, Copy)]
+pub(crate) enum Framing {
+    // `Content-Length` body, the bytes left to read
+    Length(usize),
+    // `Transfer-Encoding: chunked` body
+    Chunked(Chunk),
+}
+
+/// Position inside a chunked body
+#[derive(Clone, Copy)]
+pub(crate) enum Chunk {
+    // expecting a chunk size line
+    Size,
+    // inside a chunk, the bytes left in it (never 0)
+    Data(usize),
+    // expecting the CRLF that ends the chunk data
+    DataEnd,
+    // skipping the trailer section after the last chunk, the bytes skipped
+    // so far
+    Trailers(usize),
+    // the body is complete
+    Done,
+}
+
+// longest chunk size line or trailer section accepted
+const MAX_CHUNK_LINE: usize = 8 * 1024;
+
+// most unread body bytes, chunk framing included, discarded to keep a
+// connection alive, closing the connection is cheaper than receiving more
+const MAX_DRAIN: usize = 64 * 1024;
+
+impl Chunk {
+    /// Parse the framing at the front of `buf`
+    ///
+    /// Returns `Ok(false)` if more bytes are needed to make progress.
+    fn parse(&mut self, buf: &mut BytesMut) -> io::Result<bool> {
+        match *self {
+            Chunk::Size => {
+                let Some(line) = take_line(buf)? else {
+                    return Ok(false);
+                };
+                *self = match parse_chunk_size(&line)? {
+                    0 => Chunk::Trailers(0),
+                    n => Chunk::Data(n),
+                };
+            }
+            Chunk::DataEnd => {
+                if buf.len() < 2 {
+                    return Ok(false);
+                }
+                if &buf[..2] != b"\r\n" {
+                    return err(invalid_chunk("missing CRLF after chunk data"));
+                }
+                buf.advance(2);
+                *self = Chunk::Size;
+            }
+            Chunk::Trailers(skipped) => {
+                let Some(line) = take_line(buf)? else {
+                    if skipped + buf.len() > MAX_CHUNK_LINE {
+                        return err(invalid_chunk("trailer section too long"));
+                    }
+                    return Ok(false);
+                };
+                let skipped = skipped + line.len() + 2;
+                if skipped > MAX_CHUNK_LINE {
+                    return err(invalid_chunk("trailer section too long"));
+                }
+                // trailer fields are not exposed, an empty line ends them
+                *self = match line.is_empty() {
+                    true => Chunk::Done,
+                    false => Chunk::Trailers(skipped),
+                };
+            }
+            Chunk::Data(_) | Chunk::Done => {}
+        }
+        Ok(true)
+    }
+}
+
+/// Split a CRLF terminated line off `buf`, without the CRLF
+fn take_line(buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+    let Some(pos) = buf.iter().position(|&b| b == b'\n') else {
+        if buf.len() > MAX_CHUNK_LINE {
+            return err(invalid_chunk("chunk line too long"));
+        }
+        return Ok(None);
+    };
+    if pos == 0 || buf[pos - 1] != b'\r' {
+        return err(invalid_chunk("chunk line not terminated by CRLF"));
+    }
+    let mut line = buf.split_to(pos + 1);
+    line.truncate(pos - 1);
+    Ok(Some(line))
+}
+
+/// Parse `chunk-size [ chunk-ext ]`, the extensions are ignored
+fn parse_chunk_size(line: &[u8]) -> io::Result<usize> {
+    let size = match line.iter().position(|&b| b == b';') {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    // whitespace is allowed before the extensions
+    let size = size.trim_ascii_end();
+    if size.is_empty() {
+        return err(invalid_chunk("empty chunk size"));
+    }
+    let mut n: usize = 0;
+    for &b in size {
+        let digit = match b {
+            b'0'..=b'9' => b - b'0',
+            b'a'..=b'f' => b - b'a' + 10,
+            b'A'..=b'F' => b - b'A' + 10,
+            _ => return err(invalid_chunk("invalid chunk size")),
+        };
+        n = n
+            .checked_mul(16)
+            .and_then(|n| n.checked_add(digit as usize))
+            .ok_or_else(|| invalid_chunk("chunk size overflow"))?;
+    }
+    Ok(n)
+}
+
+/// A request body with invalid chunk framing or that ended early
+///
+/// Reads of a [`BodyReader`] fail with it as an
+/// [`io::ErrorKind::InvalidData`] or [`io::ErrorKind::UnexpectedEof`] error.
+/// The client is at fault, a service that returns it gets
+/// `400 Bad Request`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyError {
+    msg: &'static str,
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid request body: {}", self.msg)
+    }
+}
+
+impl Error for BodyError {}
+
+#[cold]
+fn invalid_chunk(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, BodyError { msg })
+}
+
+#[cold]
+fn truncated_body() -> io::Error {
+    let msg = "connection closed before the end of the body";
+    io::Error::new(io::ErrorKind::UnexpectedEof, BodyError { msg })
 }
 
 impl BodyReader<'_, '_> {
+    /// Limit the body size for this request
+    ///
+    /// Replaces the server wide
+    /// [`HttpConfig::max_body_size`](crate::HttpConfig::max_body_size), so
+    /// an upload endpoint can raise it as well. Reading a body that exceeds
+    /// the limit fails with [`io::ErrorKind::InvalidData`]; a declared
+    /// `Content-Length` over it fails the first read before anything is
+    /// received. When the service returns that error the server answers
+    /// `413 Payload Too Large` and closes the connection.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
     fn read_more_data(&mut self) -> io::Result<usize> {
+        if let Some(conn) = self.conn {
+            // the client waits for the interim response before sending the body
+            if conn.take_expect_continue() {
+                self.stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            }
+        }
         crate::http_server::reserve_buf(self.req_buf);
         let read_buf: &mut [u8] = unsafe { std::mem::transmute(self.req_buf.chunk_mut()) };
-        let n = self.stream.read(read_buf)?;
+        let n = match self.stream.read(read_buf) {
+            Ok(n) => n,
+            Err(e) => {
+                // the stream read timeout is the configured body read timeout
+                if is_timeout(&e) {
+                    if let Some(conn) = self.conn {
+                        conn.set_body_timed_out();
+                    }
+                }
+                return err(e);
+            }
+        };
         unsafe { self.req_buf.advance_mut(n) };
         Ok(n)
     }
-}
 
-impl Read for BodyReader<'_, '_> {
-    // the user should control the body reading, don't exceeds the body!
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if self.total_read >= self.body_limit {
-            return Ok(0);
+    /// Make the next body bytes available in `req_buf`
+    ///
+    /// Returns how many of the buffered bytes belong to the body, `0` once the
+    /// body is complete. After an error the end of the body is unknown, so the
+    /// connection is flagged to be closed.
+    fn fill(&mut self) -> io::Result<usize> {
+        let ret = self.fill_body();
+        if ret.is_err() {
+            if let Some(conn) = self.conn {
+                conn.set_body_failed();
+            }
         }
+        ret
+    }
 
+    fn fill_body(&mut self) -> io::Result<usize> {
         loop {
-            if !self.req_buf.is_empty() {
-                let min_len = buf.len().min(self.body_limit - self.total_read);
-                let n = self.req_buf.reader().read(&mut buf[..min_len])?;
-                self.total_read += n;
-                return Ok(n);
+            let remain = match self.framing {
+                Framing::Length(n) | Framing::Chunked(Chunk::Data(n))
+                    if n > self.limit.saturating_sub(self.total_read) =>
+                {
+                    if let Some(conn) = self.conn {
+                        conn.set_body_too_large();
+                    }
+                    return err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "request body too large",
+                    ));
+                }
+                Framing::Length(n) => n,
+                Framing::Chunked(Chunk::Data(n)) => n,
+                Framing::Chunked(Chunk::Done) => 0,
+                Framing::Chunked(mut chunk) => {
+                    let buffered = self.req_buf.len();
+                    let progress = chunk.parse(self.req_buf)?;
+                    self.framing_read += buffered - self.req_buf.len();
+                    self.framing = Framing::Chunked(chunk);
+                    if !progress && self.read_more_data()? == 0 {
+                        return err(truncated_body());
+                    }
+                    continue;
+                }
+            };
+            if remain == 0 {
+                return Ok(0);
             }
+            // the client closed the connection before the end of the body
+            if self.req_buf.is_empty() && self.read_more_data()? == 0 {
+                return err(truncated_body());
+            }
+            return Ok(self.req_buf.len().min(remain));
+        }
+    }
 
-            if self.read_more_data()? == 0 {
-                return Ok(0);
+    /// Mark `amt` body bytes at the front of `req_buf` as read
+    fn advance(&mut self, amt: usize) {
+        self.req_buf.advance(amt);
+        self.total_read += amt;
+        self.framing = match self.framing {
+            Framing::Length(n) => Framing::Length(n - amt),
+            Framing::Chunked(Chunk::Data(n)) if n == amt => Framing::Chunked(Chunk::DataEnd),
+            Framing::Chunked(Chunk::Data(n)) => Framing::Chunked(Chunk::Data(n - amt)),
+            framing => framing,
+        };
+    }
+}
+
+impl BodyReader<'_, '_> {
+    /// Discard the rest of the body, up to `budget` bytes
+    ///
+    /// The chunk framing counts against the budget as well, a body of tiny
+    /// chunks with long extensions costs as much to receive as its data.
+    /// Returns `false` if the end of the body was not reached.
+    fn drain(&mut self, budget: usize) -> bool {
+        // the limit is for the service, a known length is checked up front
+        self.limit = usize::MAX;
+        if let Framing::Length(n) = self.framing {
+            if n > budget {
+                return false;
             }
         }
+        let start = self.total_read + self.framing_read;
+        loop {
+            match self.fill_buf().map(|b| b.len()) {
+                Ok(0) => return true,
+                Ok(n) if self.total_read + self.framing_read + n - start <= budget => {
+                    self.consume(n)
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Read for BodyReader<'_, '_> {
+    // the user should control the body reading, don't exceeds the body!
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill()?.min(buf.len());
+        buf[..n].copy_from_slice(&self.req_buf[..n]);
+        self.advance(n);
+        Ok(n)
     }
 }
 
 impl BufRead for BodyReader<'_, '_> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        let remain = self.body_limit - self.total_read;
-        if remain == 0 {
-            return Ok(&[]);
-        }
-        if self.req_buf.is_empty() {
-            self.read_more_data()?;
-        }
-        let n = self.req_buf.len().min(remain);
+        let n = self.fill()?;
         Ok(&self.req_buf.chunk()[0..n])
     }
 
     fn consume(&mut self, amt: usize) {
-        assert!(amt <= self.body_limit - self.total_read);
         assert!(amt <= self.req_buf.len());
-        self.total_read += amt;
-        self.req_buf.advance(amt)
+        self.advance(amt)
     }
 }
 
 impl Drop for BodyReader<'_, '_> {
     fn drop(&mut self) {
-        // consume all the remaining bytes
-        while let Ok(n) = self.fill_buf().map(|b| b.len()) {
-            if n == 0 {
-                break;
-            }
-            // println!("drop: {:?}", n);
-            self.consume(n);
+        let Some(conn) = self.conn else {
+            self.drain(usize::MAX);
+            return;
+        };
+        // the connection is closed after a failed body read, don't wait again,
+        // and closing it beats receiving a large unread body or asking the
+        // client to send a body no one reads
+        if conn.expects_continue() || (!conn.body_failed() && !self.drain(MAX_DRAIN)) {
+            conn.set_body_failed();
         }
     }
 }
@@ -248,7 +522,10 @@ impl Drop for BodyReader<'_, '_> {
 pub struct Request<'buf, 'header, 'stream> {
     req: httparse::Request<'header, 'buf>,
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    stream: StreamRef<'stream>,
+    conn: Option<&'stream ConnState>,
+    // how the body is delimited, validated while decoding
+    framing: Framing,
 }
 
 impl<'buf, 'stream> Request<'buf, '_, 'stream> {
@@ -256,10 +533,90 @@ impl<'buf, 'stream> Request<'buf, '_, 'stream> {
         self.req.method.unwrap()
     }
 
+    /// The raw request target, including the query string
     pub fn path(&self) -> &str {
         self.req.path.unwrap()
     }
 
+    /// The connection the request arrived on, `None` for a request decoded
+    /// outside of the server
+    pub fn connection_info(&self) -> Option<&ConnectionInfo> {
+        self.conn.map(ConnState::info)
+    }
+
+    /// The address of the client
+    ///
+    /// The peer address of the connection, unless the peer is one of the
+    /// [trusted proxies](crate::ServerBuilder::trusted_proxies). Then it is
+    /// the first address of the `Forwarded` or `X-Forwarded-For` chain,
+    /// whichever the server [follows](crate::ServerBuilder::forwarded_headers),
+    /// that is not a trusted proxy, walking back from the peer, or
+    /// `X-Real-IP`.
+    /// A hop that hides its client, like `for=unknown`, ends the walk at the
+    /// proxy that reported it.
+    ///
+    /// `None` for a request decoded outside of the server or a peer address
+    /// the OS could not tell.
+    pub fn client_addr(&self) -> Option<IpAddr> {
+        match self.forwarded().and_then(|forwarded| forwarded.client) {
+            Some(client) => Some(client),
+            None => Some(self.connection_info()?.peer_addr()?.ip()),
+        }
+    }
+
+    /// The scheme the client used, `http` or `https`
+    ///
+    /// `https` only when the trusted proxy next to the client says so with
+    /// `Forwarded: proto=https` or `X-Forwarded-Proto: https`, the server
+    /// itself does not speak TLS.
+    pub fn scheme(&self) -> &'static str {
+        match self.forwarded().and_then(|forwarded| forwarded.proto) {
+            Some(proto) if proto.trim().eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        }
+    }
+
+    /// What the trusted proxies in front of the server say about the client
+    fn forwarded(&self) -> Option<Forwarded<'_>> {
+        let conn = self.conn?;
+        let info = conn.info();
+        let peer = info.peer_addr().map(|addr| addr.ip());
+        forwarded::resolve(self, peer, info.is_unix_peer(), conn.trusted_proxies())
+    }
+
+    /// The percent-decoded path of the request target, without the query
+    ///
+    /// Borrows the request when there is nothing to decode. A decoded `%2F`
+    /// is a `/` like any other, so don't split the result into segments
+    /// where that matters; invalid UTF-8 is replaced with `U+FFFD`.
+    pub fn uri_path(&self) -> Cow<'_, str> {
+        uri::percent_decode(uri::split_target(self.path()).0, false)
+    }
+
+    /// The raw query string, without the `?`
+    pub fn query(&self) -> Option<&str> {
+        uri::split_target(self.path()).1
+    }
+
+    /// The decoded `key=value` pairs of the query string
+    pub fn query_pairs(&self) -> QueryPairs<'_> {
+        QueryPairs::new(self.query().unwrap_or_default())
+    }
+
+    /// The decoded value of the first `name` query parameter
+    pub fn query_param(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The decoded values of all the `name` query parameters, in order
+    pub fn query_param_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> {
+        self.query_pairs()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
     pub fn version(&self) -> u8 {
         self.req.version.unwrap()
     }
@@ -268,24 +625,176 @@ impl<'buf, 'stream> Request<'buf, '_, 'stream> {
         self.req.headers
     }
 
+    /// The value of the first header named `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.req
+            .headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value)
+    }
+
+    /// The values of all the headers named `name` in order, ignoring case
+    pub fn header_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a [u8]> {
+        self.req
+            .headers
+            .iter()
+            .filter(move |h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value)
+    }
+
+    /// The elements of the comma separated lists of all the headers named
+    /// `name`
+    ///
+    /// Elements are trimmed, empty ones are skipped and commas inside quoted
+    /// strings don't split.
+    pub fn header_list<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a [u8]> {
+        self.header_all(name).flat_map(ListItems::new)
+    }
+
+    /// The `Host` header
+    ///
+    /// More than one `Host` header is an error. Behind a
+    /// [trusted proxy](crate::ServerBuilder::trusted_proxies) the host from
+    /// `Forwarded` or `X-Forwarded-Host` takes its place, as seen by the
+    /// proxy next to the client.
+    pub fn host(&self) -> Result<Option<Host<'_>>, HeaderError> {
+        if let Some(forwarded) = self.forwarded() {
+            if let Some(host) = forwarded.host {
+                let name = match forwarded.standard {
+                    true => "Forwarded",
+                    false => "X-Forwarded-Host",
+                };
+                return Host::parse(host.trim())
+                    .map(Some)
+                    .ok_or(HeaderError::new(name));
+            }
+        }
+        let Some(value) = headers::single("Host", self.header_all("host"))? else {
+            return Ok(None);
+        };
+        Host::parse(value).map(Some).ok_or(HeaderError::new("Host"))
+    }
+
+    /// The `Content-Type` header
+    pub fn content_type(&self) -> Result<Option<MediaType<'_>>, HeaderError> {
+        let Some(value) = headers::single("Content-Type", self.header_all("content-type"))? else {
+            return Ok(None);
+        };
+        MediaType::parse(value)
+            .map(Some)
+            .ok_or(HeaderError::new("Content-Type"))
+    }
+
+    /// The media ranges of the `Accept` headers, by descending
+    /// [`quality`](MediaType::quality)
+    ///
+    /// Empty if the client accepts anything.
+    pub fn accept(&self) -> Result<Vec<MediaType<'_>>, HeaderError> {
+        let mut ranges = self
+            .header_list("accept")
+            .map(|item| {
+                headers::to_str("Accept", item)
+                    .ok()
+                    .and_then(MediaType::parse)
+                    .ok_or(HeaderError::new("Accept"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        ranges.sort_by_key(|range| std::cmp::Reverse(range.quality()));
+        Ok(ranges)
+    }
+
+    /// The `Authorization` header
+    pub fn authorization(&self) -> Result<Option<Authorization<'_>>, HeaderError> {
+        let Some(value) = headers::single("Authorization", self.header_all("authorization"))?
+        else {
+            return Ok(None);
+        };
+        Authorization::parse(value)
+            .map(Some)
+            .ok_or(HeaderError::new("Authorization"))
+    }
+
+    /// The options of the `Connection` headers
+    pub fn connection(&self) -> Connection {
+        let mut connection = Connection::default();
+        for option in self.header_list("connection") {
+            connection.add(option);
+        }
+        connection
+    }
+
+    /// The `If-None-Match` headers
+    pub fn if_none_match(&self) -> Result<Option<IfNoneMatch<'_>>, HeaderError> {
+        let invalid = || HeaderError::new("If-None-Match");
+        let mut tags = Vec::new();
+        for item in self.header_list("if-none-match") {
+            if item == b"*" {
+                return Ok(Some(IfNoneMatch::Any));
+            }
+            let tag = headers::to_str("If-None-Match", item)?;
+            tags.push(EntityTag::parse(tag).ok_or_else(invalid)?);
+        }
+        if tags.is_empty() {
+            // present but empty is as invalid as a bad tag
+            return match self.header("if-none-match") {
+                Some(_) => Err(invalid()),
+                None => Ok(None),
+            };
+        }
+        Ok(Some(IfNoneMatch::Tags(tags)))
+    }
+
+    /// The byte ranges of the `Range` header
+    ///
+    /// A range in another unit than `bytes` is ignored.
+    pub fn range(&self) -> Result<Option<Vec<ByteRange>>, HeaderError> {
+        match headers::single("Range", self.header_all("range"))? {
+            Some(value) => headers::parse_range(value),
+            None => Ok(None),
+        }
+    }
+
+    /// The `name=value` pairs of the `Cookie` headers
+    pub fn cookies(&self) -> Cookies<'_> {
+        Cookies::new(self.req.headers)
+    }
+
+    /// The value of the first cookie named `name`
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// The request body
+    ///
+    /// `Transfer-Encoding: chunked` bodies are decoded transparently, chunk
+    /// extensions and trailer fields are skipped. Bytes the service does not
+    /// read are discarded when the reader is dropped.
+    ///
+    /// A client that sent `Expect: 100-continue` gets `100 Continue` on the
+    /// first read that needs the body. Answer without reading the body to
+    /// reject the request, the connection is closed after the response.
     pub fn body(self) -> BodyReader<'buf, 'stream> {
+        if let Some(conn) = self.conn {
+            conn.set_unread_body(None);
+        }
         BodyReader {
-            body_limit: self.content_length(),
+            framing: self.framing,
+            limit: self.conn.map_or(usize::MAX, ConnState::max_body_size),
             total_read: 0,
+            framing_read: 0,
             stream: self.stream,
             req_buf: self.req_buf,
+            conn: self.conn,
         }
     }
 
-    fn content_length(&self) -> usize {
-        let mut len = 0;
-        for header in self.req.headers.iter() {
-            if header.name.eq_ignore_ascii_case("content-length") {
-                len = std::str::from_utf8(header.value).unwrap().parse().unwrap();
-                break;
-            }
+    /// The declared `Content-Length` of the body, `None` for a chunked body
+    pub fn content_length(&self) -> Option<usize> {
+        match self.framing {
+            Framing::Length(n) => Some(n),
+            Framing::Chunked(_) => None,
         }
-        len
     }
 }
 
@@ -295,11 +804,12 @@ impl fmt::Debug for Request<'_, '_, '_> {
     }
 }
 
-pub fn decode<'header, 'buf, 'stream, const N: usize>(
-    headers: &'header mut [MaybeUninit<httparse::Header<'buf>>; N],
+pub(crate) fn decode<'header, 'buf, 'stream>(
+    headers: &'header mut [MaybeUninit<httparse::Header<'buf>>],
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
-) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
+    stream: StreamRef<'stream>,
+    conn: Option<&'stream ConnState>,
+) -> Result<Option<Request<'buf, 'header, 'stream>>, ParseError> {
     let mut req = httparse::Request::new(&mut []);
     // safety: don't hold the reference of req_buf
     // so we can transfer the mutable reference to Request
@@ -326,57 +836,224 @@ pub fn decode<'header, 'buf, 'stream, const N: usize>(
 
     let status = match req.parse_with_uninit_headers(buf, headers) {
         Ok(s) => s,
-        Err(e) => {
-            // Provide detailed error message for TooManyHeaders
-            let msg = if e == httparse::Error::TooManyHeaders {
-                // Count how many headers were actually sent
-                let header_count = buf
-                    .split(|&b| b == b'\n')
-                    .filter(|line| {
-                        !line.is_empty() && line.contains(&b':') && !line.starts_with(b"\r\n")
-                    })
-                    .count();
-
-                let over_by = header_count.saturating_sub(header_limit);
-
-                let error_msg = format!(
-                    "TooManyHeaders: received {header_count} headers, limit is {header_limit} (over by {over_by})"
-                );
-
-                // Log the error
-                eprintln!("{error_msg}");
-
-                // Log the suggestion on a separate line for clarity
-                eprintln!(
-                    "Suggestion: Consider using MaxHeaders::Standard (32), \
-                     MaxHeaders::Large (64), or MaxHeaders::XLarge (128) for production deployments."
-                );
-
-                error_msg
-            } else {
-                let error_msg = format!("failed to parse http request: {e:?}");
-                eprintln!("{error_msg}");
-                error_msg
-            };
-
-            return err(io::Error::other(msg));
-        }
+        Err(e) => return Err(parse_error(e, buf, header_limit)),
     };
 
+    // the target is known once the request line is parsed, before that the
+    // pending request line is at least as long as the buffered bytes
+    let max_uri_len = conn.map_or(usize::MAX, ConnState::max_uri_len);
+    if req.path.map_or(buf.len(), str::len) > max_uri_len {
+        return Err(ParseError::UriTooLong { limit: max_uri_len });
+    }
+
+    // an incomplete head is at least one byte longer than what is buffered
+    let max_head_size = conn.map_or(usize::MAX, ConnState::max_head_size);
     let len = match status {
-        httparse::Status::Complete(amt) => amt,
-        httparse::Status::Partial => return Ok(None),
+        httparse::Status::Complete(amt) if amt <= max_head_size => amt,
+        httparse::Status::Partial if buf.len() < max_head_size => return Ok(None),
+        _ => {
+            return Err(ParseError::HeadTooLarge {
+                limit: max_head_size,
+            })
+        }
     };
-    req_buf.advance(len);
+    let framing = body_framing(req.headers)?;
+    if let Some(conn) = conn {
+        // cleared once the service asks for the body
+        conn.set_unread_body(match framing {
+            Framing::Length(0) => None,
+            framing => Some(framing),
+        });
+        let http10 = req.version == Some(0);
+        let head = req.method == Some("HEAD");
+        conn.set_request(http10, head, is_persistent(http10, req.headers));
+        // HTTP/1.0 clients don't know the interim response
+        let buffered = buf.len() - len;
+        conn.set_expect_continue(
+            !http10
+                && match framing {
+                    Framing::Length(n) => n > buffered,
+                    Framing::Chunked(_) => buffered == 0,
+                }
+                && expects_continue(req.headers),
+        );
+        // reading the body may move the rest of the buffer, but not the head
+        let line = req
+            .method
+            .zip(req.path)
+            .map_or((0, 0), |(method, path)| request_line(buf, method, path));
+        conn.set_head(req_buf.split_to(len), line);
+    } else {
+        req_buf.advance(len);
+    }
 
     // println!("req: {:?}", std::str::from_utf8(req_buf).unwrap());
     Ok(Some(Request {
         req,
         req_buf,
         stream,
+        conn,
+        framing,
     }))
 }
 
+/// Whether the client waits for `100 Continue` before sending the body
+fn expects_continue(headers: &[httparse::Header<'_>]) -> bool {
+    headers.iter().any(|h| {
+        h.name.eq_ignore_ascii_case("expect")
+            && h.value.trim_ascii().eq_ignore_ascii_case(b"100-continue")
+    })
+}
+
+/// Whether the client keeps the connection open after this request (RFC 9112
+/// section 9.3)
+///
+/// HTTP/1.1 connections persist unless the client sends `Connection: close`,
+/// HTTP/1.0 ones only if it asks for `keep-alive`.
+fn is_persistent(http10: bool, headers: &[httparse::Header<'_>]) -> bool {
+    let mut connection = Connection::default();
+    headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case("connection"))
+        .flat_map(|h| ListItems::new(h.value))
+        .for_each(|option| connection.add(option));
+    !connection.is_close() && (!http10 || connection.is_keep_alive())
+}
+
+/// Find how the body is delimited (RFC 9112 section 6)
+///
+/// Requests carrying both `Content-Length` and `Transfer-Encoding` are
+/// rejected rather than picking one, a proxy in front of us may have picked
+/// the other and the difference is what request smuggling exploits.
+fn body_framing(headers: &[httparse::Header<'_>]) -> Result<Framing, ParseError> {
+    let mut length = None;
+    let mut chunked = None;
+    for h in headers {
+        if h.name.eq_ignore_ascii_case("content-length") {
+            let len = parse_content_length(h.value)?;
+            if length.is_some_and(|l| l != len) {
+                return Err(ParseError::InvalidContentLength);
+            }
+            length = Some(len);
+        } else if h.name.eq_ignore_ascii_case("transfer-encoding") {
+            // the codings of all the fields form one list, the last is final
+            chunked = Some(
+                h.value
+                    .rsplit(|&b| b == b',')
+                    .next()
+                    .is_some_and(|coding| coding.trim_ascii().eq_ignore_ascii_case(b"chunked")),
+            );
+        }
+    }
+    match (length, chunked) {
+        (Some(_), Some(_)) => Err(ParseError::ContentLengthWithTransferEncoding),
+        (None, Some(true)) => Ok(Framing::Chunked(Chunk::Size)),
+        (None, Some(false)) => Err(ParseError::InvalidTransferEncoding),
+        (len, None) => Ok(Framing::Length(len.unwrap_or(0))),
+    }
+}
+
+/// Parse a `Content-Length` value
+///
+/// A list of identical values is accepted as a single one, anything but
+/// digits, an empty value or a value that overflows is invalid.
+fn parse_content_length(value: &[u8]) -> Result<usize, ParseError> {
+    let mut length = None;
+    for part in value.split(|&b| b == b',') {
+        let part = part.trim_ascii();
+        if part.is_empty() {
+            return Err(ParseError::InvalidContentLength);
+        }
+        let mut n: usize = 0;
+        for &b in part {
+            if !b.is_ascii_digit() {
+                return Err(ParseError::InvalidContentLength);
+            }
+            n = n
+                .checked_mul(10)
+                .and_then(|n| n.checked_add((b - b'0') as usize))
+                .ok_or(ParseError::InvalidContentLength)?;
+        }
+        if length.is_some_and(|l| l != n) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        length = Some(n);
+    }
+    length.ok_or(ParseError::InvalidContentLength)
+}
+
+/// Classify a parse failure
+///
+/// A version error is only "not supported" for a well formed `HTTP/x.y`
+/// token, anything else in that position is a bad request.
+#[cold]
+fn parse_error(e: httparse::Error, buf: &[u8], header_limit: usize) -> ParseError {
+    match e {
+        httparse::Error::TooManyHeaders => ParseError::TooManyHeaders {
+            received: count_header_lines(buf),
+            limit: header_limit,
+        },
+        httparse::Error::Version if is_http_version(request_line_version(buf)) => {
+            ParseError::VersionNotSupported
+        }
+        e => ParseError::BadRequest(e),
+    }
+}
+
+/// Count the header lines of the first request head in `buf`
+fn count_header_lines(buf: &[u8]) -> usize {
+    let head_end = buf
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .unwrap_or(buf.len());
+    buf[..head_end]
+        .split(|&b| b == b'\n')
+        .skip(1)
+        .filter(|line| line.contains(&b':'))
+        .count()
+}
+
+/// Start and length of `METHOD path` of a request parsed from `buf`
+fn request_line(buf: &[u8], method: &str, path: &str) -> (usize, usize) {
+    let start = method.as_ptr() as usize - buf.as_ptr() as usize;
+    let end = path.as_ptr() as usize + path.len() - buf.as_ptr() as usize;
+    (start, end - start)
+}
+
+/// The last token of the request line
+fn request_line_version(buf: &[u8]) -> &[u8] {
+    let line = buf.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    line.rsplit(|&b| b == b' ').next().unwrap_or_default()
+}
+
+fn is_http_version(v: &[u8]) -> bool {
+    matches!(v, [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+        if major.is_ascii_digit() && minor.is_ascii_digit())
+}
+
+/// Skip the body of a request whose service never asked for it
+///
+/// A body that is already buffered is dropped, anything else would have to be
+/// received first, so the connection is flagged to be closed instead.
+pub(crate) fn skip_unread_body(conn: &ConnState, req_buf: &mut BytesMut) {
+    // a client still waiting for `100 Continue` doesn't send the body
+    conn.set_expect_continue(false);
+    match conn.take_unread_body() {
+        None => {}
+        Some(Framing::Length(n)) if n <= req_buf.len() => req_buf.advance(n),
+        Some(_) => conn.set_body_failed(),
+    }
+}
+
+/// Check whether `buf` starts with a complete request head
+///
+/// Only used off the hot path, e.g. to find the last pipelined request
+/// while the server is draining.
+pub(crate) fn is_head_complete(buf: &[u8]) -> bool {
+    buf.windows(4).any(|w| w == b"\r\n\r\n")
+}
+
 /// Decode HTTP request with Default (16) headers
 ///
 /// # Errors
@@ -390,7 +1067,7 @@ pub fn decode_default<'header, 'buf, 'stream>(
     req_buf: &'buf mut BytesMut,
     stream: &'stream mut TcpStream,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, StreamRef::Tcp(stream), None).map_err(io::Error::from)
 }
 
 /// Decode HTTP request with Standard (32) headers
@@ -406,7 +1083,7 @@ pub fn decode_standard<'header, 'buf, 'stream>(
     req_buf: &'buf mut BytesMut,
     stream: &'stream mut TcpStream,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, StreamRef::Tcp(stream), None).map_err(io::Error::from)
 }
 
 /// Decode HTTP request with Large (64) headers
@@ -422,7 +1099,7 @@ pub fn decode_large<'header, 'buf, 'stream>(
     req_buf: &'buf mut BytesMut,
     stream: &'stream mut TcpStream,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, StreamRef::Tcp(stream), None).map_err(io::Error::from)
 }
 
 /// Decode HTTP request with `XLarge` (128) headers
@@ -438,5 +1115,37 @@ pub fn decode_xlarge<'header, 'buf, 'stream>(
     req_buf: &'buf mut BytesMut,
     stream: &'stream mut TcpStream,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, StreamRef::Tcp(stream), None).map_err(io::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_size_is_hex() {
+        assert_eq!(parse_chunk_size(b"0").unwrap(), 0);
+        assert_eq!(parse_chunk_size(b"1a").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"FF").unwrap(), 255);
+    }
+
+    /// Extensions and the whitespace before them are ignored
+    #[test]
+    fn chunk_extensions_are_ignored() {
+        assert_eq!(parse_chunk_size(b"5;name=value").unwrap(), 5);
+        assert_eq!(parse_chunk_size(b"5 \t;a;b=\"c\"").unwrap(), 5);
+    }
+
+    #[test]
+    fn invalid_chunk_sizes() {
+        for line in [&b""[..], b";ext", b"-1", b"0x5", b" 5", b"5 5", b"g"] {
+            assert!(
+                parse_chunk_size(line).is_err(),
+                "{}",
+                String::from_utf8_lossy(line)
+            );
+        }
+        // one digit more than a 64 bit size
+        assert!(parse_chunk_size(&[b'f'; 17]).is_err());
+    }
 }
@@ -1,7 +1,11 @@
 use std::borrow::Cow;
-use std::io;
+use std::fmt::Write as _;
+use std::io::{self, Write};
 
-use crate::request::MAX_HEADERS;
+use crate::connection::ConnState;
+use crate::listener::StreamRef;
+use crate::response_headers::{self, ResponseHeaders};
+use crate::status::{InvalidStatusCode, StatusCode};
 
 use bytes::BytesMut;
 
@@ -104,13 +108,34 @@ impl IntoResponseHeader for ResponseHeader {
 }
 
 pub struct Response<'a> {
-    headers: [ResponseHeader; MAX_HEADERS],
-    headers_len: usize,
+    headers: ResponseHeaders,
     status_message: StatusMessage,
     body: Body,
     rsp_buf: &'a mut BytesMut,
+    // the connection the response goes to, `None` for a detached response
+    conn: Option<ConnOut<'a>>,
+    // the head was sent by `chunked`, the body is streamed
+    streaming: bool,
+    // answers an HTTP/1.0 request
+    http10: bool,
+    // answers a HEAD request, the body is not sent
+    head: bool,
+    // the connection is closed after this response
+    close: bool,
+    // the head is checked before it is written
+    check_head: bool,
 }
 
+/// Output side of the serving connection
+struct ConnOut<'a> {
+    stream: StreamRef<'a>,
+    // encoded responses of earlier pipelined requests, not yet written
+    pending: &'a mut BytesMut,
+}
+
+/// Data buffered by a [`ChunkedWriter`] before it is sent as a chunk
+const CHUNK_SIZE: usize = 8 * 1024;
+
 enum Body {
     Str(&'static str),
     Vec(Vec<u8>),
@@ -118,36 +143,180 @@ enum Body {
 }
 
 struct StatusMessage {
-    code: usize,
+    code: StatusCode,
     msg: &'static str,
+    // the precomputed status line when `msg` is the canonical reason phrase
+    line: Option<&'static [u8]>,
+}
+
+impl StatusMessage {
+    fn new(code: StatusCode) -> Self {
+        StatusMessage {
+            code,
+            msg: code.canonical_reason().unwrap_or(""),
+            line: code.status_line(),
+        }
+    }
+
+    fn with_reason(code: StatusCode, msg: &'static str) -> Self {
+        let line = match code.canonical_reason() {
+            Some(reason) if reason == msg => code.status_line(),
+            _ => None,
+        };
+        StatusMessage { code, msg, line }
+    }
 }
 
 impl<'a> Response<'a> {
     pub(crate) fn new(rsp_buf: &'a mut BytesMut) -> Response<'a> {
         Response {
-            headers: std::array::from_fn(|_| ResponseHeader::Static("")),
-            headers_len: 0,
+            headers: ResponseHeaders::new(),
             body: Body::Dummy,
-            status_message: StatusMessage {
-                code: 200,
-                msg: "Ok",
-            },
+            status_message: StatusMessage::new(StatusCode::OK),
             rsp_buf,
+            conn: None,
+            streaming: false,
+            http10: false,
+            head: false,
+            close: false,
+            check_head: cfg!(debug_assertions),
         }
     }
 
+    /// Create a response that can stream its body to `stream`
+    ///
+    /// `pending` holds the encoded responses that must go out first, the
+    /// response itself is encoded into it as well. `state` tells the version
+    /// and persistence of the request being answered.
+    pub(crate) fn with_conn(
+        rsp_buf: &'a mut BytesMut,
+        stream: StreamRef<'a>,
+        pending: &'a mut BytesMut,
+        state: &ConnState,
+    ) -> Response<'a> {
+        let mut rsp = Response::new(rsp_buf);
+        rsp.conn = Some(ConnOut { stream, pending });
+        rsp.http10 = state.is_http10();
+        rsp.head = state.is_head();
+        rsp.close = !state.keep_alive();
+        rsp.check_head = state.check_headers();
+        rsp
+    }
+
+    /// Detach the response from the connection, returns the buffer that the
+    /// response is to be encoded into
+    #[inline]
+    pub(crate) fn take_pending(&mut self) -> Option<&'a mut BytesMut> {
+        self.conn.take().map(|conn| conn.pending)
+    }
+
+    /// The body is streamed by a [`ChunkedWriter`]
+    #[inline]
+    pub(crate) fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// The connection is closed after this response
+    #[inline]
+    pub(crate) fn closes_connection(&self) -> bool {
+        self.close
+    }
+
+    /// Answers an HTTP/1.0 request
+    #[inline]
+    pub(crate) fn is_http10(&self) -> bool {
+        self.http10
+    }
+
+    /// Drop everything the service set, for a response built from scratch
+    pub(crate) fn reset(&mut self, status: StatusCode) {
+        self.status_message = StatusMessage::new(status);
+        self.headers = ResponseHeaders::new();
+        self.body = Body::Dummy;
+        self.rsp_buf.clear();
+    }
+
+    /// Check the head before it is written
+    #[inline]
+    pub(crate) fn set_check_head(&mut self, check: bool) {
+        self.check_head = check;
+    }
+
+    /// Fail if the reason phrase or a header would break the response head,
+    /// see [`HttpConfig::strict_headers`](crate::HttpConfig::strict_headers)
+    pub(crate) fn check_head(&self) -> io::Result<()> {
+        if !self.check_head {
+            return Ok(());
+        }
+        if !response_headers::is_field_text(self.status_message.msg) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid response reason phrase",
+            ));
+        }
+        self.headers.validate()
+    }
+
+    /// Close the connection once this response is sent
+    ///
+    /// The response carries `Connection: close` and pipelined requests
+    /// received after this one are not served.
+    #[inline]
+    pub fn close_connection(&mut self) -> &mut Self {
+        self.close = true;
+        self
+    }
+
+    /// Set the status with its canonical reason phrase, `200 OK` by default
+    ///
+    /// An unregistered code gets an empty reason phrase.
+    #[inline]
+    pub fn status(&mut self, status: StatusCode) -> &mut Self {
+        self.status_message = StatusMessage::new(status);
+        self
+    }
+
+    /// Set the status with a custom reason phrase
+    ///
+    /// Prefer [`status`](Self::status), the canonical reason phrase gets the
+    /// precomputed status line. A `code` outside of 100 to 999 is a bug, it
+    /// fails a debug assertion and sends `500 Internal Server Error` in
+    /// release builds; use [`try_status_code`](Self::try_status_code) for a
+    /// code that is not known to be valid.
     #[inline]
     pub fn status_code(&mut self, code: usize, msg: &'static str) -> &mut Self {
-        self.status_message = StatusMessage { code, msg };
+        let valid = u16::try_from(code)
+            .ok()
+            .and_then(|code| StatusCode::from_u16(code).ok());
+        debug_assert!(valid.is_some(), "invalid status code {code}");
+        self.status_message = match valid {
+            Some(code) => StatusMessage::with_reason(code, msg),
+            None => StatusMessage::new(StatusCode::INTERNAL_SERVER_ERROR),
+        };
         self
     }
 
+    /// Set the status with a custom reason phrase, fails unless `code` is
+    /// within 100 to 999
+    #[inline]
+    pub fn try_status_code(
+        &mut self,
+        code: u16,
+        msg: &'static str,
+    ) -> Result<&mut Self, InvalidStatusCode> {
+        let code = StatusCode::from_u16(code)?;
+        self.status_message = StatusMessage::with_reason(code, msg);
+        Ok(self)
+    }
+
     /// Append a header line to the response.
     ///
     /// Accepts both `&'static str` (zero allocation, identical to the previous
     /// behavior) and owned strings (`String`, `Box<str>`, `Cow<'static, str>`).
     /// Owned header values are freed when the response is dropped — there is
-    /// no need for callers to `Box::leak` formatted values.
+    /// no need for callers to `Box::leak` formatted values. Use
+    /// [`headers_mut`](Self::headers_mut) to inspect, replace or remove
+    /// headers.
     ///
     /// ```
     /// # use may_minihttp::{Response, ResponseHeader};
@@ -163,11 +332,22 @@ impl<'a> Response<'a> {
     /// ```
     #[inline]
     pub fn header<H: IntoResponseHeader>(&mut self, header: H) -> &mut Self {
-        self.headers[self.headers_len] = header.into_response_header();
-        self.headers_len += 1;
+        self.headers.append_line(header.into_response_header());
         self
     }
 
+    /// The headers set so far
+    #[inline]
+    pub fn headers(&self) -> &ResponseHeaders {
+        &self.headers
+    }
+
+    /// The headers set so far, to inspect, replace or remove them
+    #[inline]
+    pub fn headers_mut(&mut self) -> &mut ResponseHeaders {
+        &mut self.headers
+    }
+
     #[inline]
     pub fn body(&mut self, s: &'static str) {
         self.body = Body::Str(s);
@@ -203,6 +383,13 @@ impl<'a> Response<'a> {
         }
     }
 
+    /// Whether the body is sent, a response to `HEAD` and the `1xx`, `204`
+    /// and `304` statuses have none (RFC 9110 section 6.4.1)
+    #[inline]
+    fn has_body(&self) -> bool {
+        !self.head && !matches!(self.status_message.code.as_u16(), 100..=199 | 204 | 304)
+    }
+
     #[inline]
     fn get_body(&mut self) -> &[u8] {
         match self.body {
@@ -212,6 +399,77 @@ impl<'a> Response<'a> {
         }
     }
 
+    /// Stream the body with `Transfer-Encoding: chunked`
+    ///
+    /// The status line and the headers set so far are written to the
+    /// connection right away, so set them before calling this. Data written
+    /// to the returned writer goes out as a chunk whenever 8 KiB are buffered
+    /// or on [`flush`](Write::flush), and a body set before is sent as the
+    /// first chunk. The last chunk is sent once the service returns `Ok`; if
+    /// it returns an error instead the connection is closed, which tells the
+    /// client that the body is incomplete.
+    ///
+    /// ```no_run
+    /// use may_minihttp::{HttpService, Request, Response};
+    /// use std::io::{self, Write};
+    ///
+    /// struct Export;
+    ///
+    /// impl HttpService for Export {
+    ///     fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+    ///         rsp.header("Content-Type: text/csv");
+    ///         let mut body = rsp.chunked()?;
+    ///         for i in 0..1_000_000 {
+    ///             writeln!(body, "{i},{}", i * i)?;
+    ///         }
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if the response is not attached to a connection, a header is
+    /// invalid or the head cannot be written.
+    pub fn chunked(&mut self) -> io::Result<ChunkedWriter<'_, 'a>> {
+        if !self.streaming {
+            self.check_head()?;
+            // a body set before becomes the first chunk
+            self.body_mut();
+            let has_body = self.has_body();
+            let Some(conn) = self.conn.as_mut() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "response is not attached to a connection",
+                ));
+            };
+            encode_head(
+                &self.status_message,
+                self.http10,
+                &self.headers,
+                conn.pending,
+            );
+            if !has_body {
+                // the data written is dropped
+            } else if self.http10 {
+                // HTTP/1.0 has no chunked coding, closing the connection
+                // ends the body
+                self.close = true;
+            } else {
+                conn.pending
+                    .extend_from_slice(b"\r\nTransfer-Encoding: chunked");
+            }
+            if self.close {
+                conn.pending.extend_from_slice(b"\r\nConnection: close");
+            }
+            self.headers.encode(conn.pending);
+            conn.pending.extend_from_slice(b"\r\n\r\n");
+            self.streaming = true;
+            conn.flush()?;
+        }
+        Ok(ChunkedWriter { rsp: self })
+    }
+
     /// Test-only constructor used by the doc example above. Not part of the
     /// public API; gated so downstream crates cannot accidentally rely on it.
     #[doc(hidden)]
@@ -220,6 +478,59 @@ impl<'a> Response<'a> {
     }
 }
 
+impl ConnOut<'_> {
+    /// Write out the pending bytes
+    fn flush(&mut self) -> io::Result<()> {
+        let mut stream = self.stream;
+        let ret = stream.write_all(self.pending);
+        self.pending.clear();
+        ret
+    }
+}
+
+/// Writer of a streamed response body, see [`Response::chunked`]
+pub struct ChunkedWriter<'r, 'a> {
+    rsp: &'r mut Response<'a>,
+}
+
+impl ChunkedWriter<'_, '_> {
+    /// Send the buffered data as one chunk
+    fn send_chunk(&mut self) -> io::Result<()> {
+        let rsp = &mut *self.rsp;
+        if !rsp.has_body() {
+            rsp.rsp_buf.clear();
+        }
+        if rsp.rsp_buf.is_empty() {
+            return Ok(());
+        }
+        // only a response attached to a connection can start streaming
+        let Some(conn) = rsp.conn.as_mut() else {
+            return Ok(());
+        };
+        if rsp.http10 {
+            conn.pending.extend_from_slice(rsp.rsp_buf);
+        } else {
+            encode_chunk(rsp.rsp_buf, conn.pending);
+        }
+        rsp.rsp_buf.clear();
+        conn.flush()
+    }
+}
+
+impl Write for ChunkedWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.rsp.rsp_buf.extend_from_slice(buf);
+        if self.rsp.rsp_buf.len() >= CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_chunk()
+    }
+}
+
 impl Drop for Response<'_> {
     fn drop(&mut self) {
         self.rsp_buf.clear();
@@ -227,46 +538,107 @@ impl Drop for Response<'_> {
 }
 
 pub(crate) fn encode(mut rsp: Response, buf: &mut BytesMut) {
-    if rsp.status_message.code == 200 {
-        buf.extend_from_slice(b"HTTP/1.1 200 Ok\r\nServer: M\r\nDate: ");
-    } else {
-        buf.extend_from_slice(b"HTTP/1.1 ");
-        let mut code = itoa::Buffer::new();
-        buf.extend_from_slice(code.format(rsp.status_message.code).as_bytes());
-        buf.extend_from_slice(b" ");
-        buf.extend_from_slice(rsp.status_message.msg.as_bytes());
-        buf.extend_from_slice(b"\r\nServer: M\r\nDate: ");
+    encode_head(&rsp.status_message, rsp.http10, &rsp.headers, buf);
+    // a response to HEAD tells the length of the body it leaves out, which
+    // only the service knows if it didn't set the body
+    if !matches!(rsp.status_message.code.as_u16(), 100..=199 | 204 | 304) {
+        buf.extend_from_slice(b"\r\nContent-Length: ");
+        match rsp.headers.content_length() {
+            Some(length) if rsp.head => buf.extend_from_slice(length.as_bytes()),
+            _ => {
+                let mut length = itoa::Buffer::new();
+                buf.extend_from_slice(length.format(rsp.body_len()).as_bytes());
+            }
+        }
+    }
+
+    rsp.headers.encode(buf);
+
+    buf.extend_from_slice(b"\r\n\r\n");
+    if rsp.has_body() {
+        buf.extend_from_slice(rsp.get_body());
+    }
+}
+
+/// Encode the status line and the `Server` and `Date` headers
+///
+/// The version of an HTTP/1.0 request is echoed, so the client doesn't
+/// expect HTTP/1.1 features like the chunked coding.
+#[inline]
+fn encode_status(status: &StatusMessage, http10: bool, buf: &mut BytesMut) {
+    match status.line {
+        Some(line) if !http10 => buf.extend_from_slice(line),
+        _ => {
+            encode_status_line(status, http10, buf);
+            buf.extend_from_slice(b"\r\nServer: M\r\nDate: ");
+        }
     }
     crate::date::append_date(buf);
-    buf.extend_from_slice(b"\r\nContent-Length: ");
-    let mut length = itoa::Buffer::new();
-    buf.extend_from_slice(length.format(rsp.body_len()).as_bytes());
+}
 
-    // SAFETY: we already have bound check when insert headers
-    let headers = unsafe { rsp.headers.get_unchecked(..rsp.headers_len) };
-    for h in headers {
-        buf.extend_from_slice(b"\r\n");
-        buf.extend_from_slice(h.as_bytes());
+/// Encode the status line and the `Server` and `Date` headers, the service
+/// may have set its own
+#[inline]
+fn encode_head(
+    status: &StatusMessage,
+    http10: bool,
+    headers: &ResponseHeaders,
+    buf: &mut BytesMut,
+) {
+    if !headers.overrides_head() {
+        return encode_status(status, http10, buf);
+    }
+    encode_status_line(status, http10, buf);
+    buf.extend_from_slice(b"\r\nServer: ");
+    let server = headers.get("server").unwrap_or("M");
+    buf.extend_from_slice(server.as_bytes());
+    buf.extend_from_slice(b"\r\nDate: ");
+    match headers.get("date") {
+        Some(date) => buf.extend_from_slice(date.as_bytes()),
+        None => crate::date::append_date(buf),
     }
+}
 
-    buf.extend_from_slice(b"\r\n\r\n");
-    buf.extend_from_slice(rsp.get_body());
+/// Encode the status line without its line break
+fn encode_status_line(status: &StatusMessage, http10: bool, buf: &mut BytesMut) {
+    buf.extend_from_slice(if http10 { b"HTTP/1.0 " } else { b"HTTP/1.1 " });
+    let mut code = itoa::Buffer::new();
+    buf.extend_from_slice(code.format(status.code.as_u16()).as_bytes());
+    buf.extend_from_slice(b" ");
+    buf.extend_from_slice(status.msg.as_bytes());
 }
 
-#[cold]
-pub(crate) fn encode_error(e: io::Error, buf: &mut BytesMut) {
-    error!("error in service: err = {e:?}");
-    let msg_string = e.to_string();
-    let msg = msg_string.as_bytes();
+/// Encode `data` as one chunk of a chunked body
+fn encode_chunk(data: &[u8], buf: &mut BytesMut) {
+    // writing to a `BytesMut` never fails
+    let _ = write!(buf, "{:x}\r\n", data.len());
+    buf.extend_from_slice(data);
+    buf.extend_from_slice(b"\r\n");
+}
 
-    buf.extend_from_slice(b"HTTP/1.1 500 Internal Server Error\r\nServer: M\r\nDate: ");
-    crate::date::append_date(buf);
-    buf.extend_from_slice(b"\r\nContent-Length: ");
-    let mut length = itoa::Buffer::new();
-    buf.extend_from_slice(length.format(msg.len()).as_bytes());
+/// Encode the end of a streamed body, the data still buffered goes out as
+/// the last chunk before the terminating one
+pub(crate) fn encode_last_chunk(rsp: Response, buf: &mut BytesMut) {
+    if !rsp.has_body() {
+        return;
+    }
+    if rsp.http10 {
+        // the body ends with the connection
+        buf.extend_from_slice(rsp.rsp_buf);
+        return;
+    }
+    if !rsp.rsp_buf.is_empty() {
+        encode_chunk(rsp.rsp_buf, buf);
+    }
+    buf.extend_from_slice(b"0\r\n\r\n");
+}
 
-    buf.extend_from_slice(b"\r\n\r\n");
-    buf.extend_from_slice(msg);
+/// Encode a bodiless response for a request the server gave up on, the
+/// connection is closed right after it
+#[cold]
+pub(crate) fn encode_close(status: StatusCode, buf: &mut BytesMut) {
+    encode_status(&StatusMessage::new(status), false, buf);
+    buf.extend_from_slice(b"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
 }
 
 #[cfg(test)]
@@ -321,7 +693,7 @@ mod tests {
             encode(res, &mut out);
         }
         let response_str = std::str::from_utf8(&out).expect("utf8");
-        assert!(response_str.starts_with("HTTP/1.1 200 Ok\r\n"));
+        assert!(response_str.starts_with("HTTP/1.1 200 OK\r\n"));
         assert!(response_str.contains("\r\nContent-Type: application/json\r\n"));
         assert!(response_str.contains("\r\nX-Request-ID: 01J\r\n"));
         assert!(response_str.contains("\r\nX-Trace: abc\r\n"));
@@ -0,0 +1,290 @@
+//! the headers of a response
+
+use std::io;
+
+use bytes::BytesMut;
+use smallvec::SmallVec;
+
+use crate::headers::is_token;
+use crate::response::{IntoResponseHeader, ResponseHeader};
+
+// headers kept inside the response, more spill to the heap
+const INLINE_HEADERS: usize = 16;
+
+// headers the server writes on its own, a value set by the service is used
+// instead of the default one
+const CONTENT_LENGTH: u8 = 1;
+const DATE: u8 = 2;
+const SERVER: u8 = 4;
+
+/// A header as the service set it
+#[derive(Debug)]
+enum Entry {
+    /// A whole `Name: value` line, `colon` is the offset of the `:`
+    Line { line: ResponseHeader, colon: usize },
+    /// A name and a value set separately
+    Pair {
+        name: ResponseHeader,
+        value: ResponseHeader,
+    },
+}
+
+impl Entry {
+    fn line(line: ResponseHeader) -> Self {
+        let colon = line.as_str().find(':').unwrap_or(line.as_str().len());
+        Entry::Line { line, colon }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Entry::Line { line, colon } => line.as_str()[..*colon].trim_end(),
+            Entry::Pair { name, .. } => name.as_str(),
+        }
+    }
+
+    fn value(&self) -> &str {
+        match self {
+            Entry::Line { line, colon } => line
+                .as_str()
+                .get(colon + 1..)
+                .unwrap_or_default()
+                .trim_matches([' ', '\t']),
+            Entry::Pair { value, .. } => value.as_str(),
+        }
+    }
+
+    /// Whether the header can be written as is, a `Name: value` line with a
+    /// token name and no line break or NUL in the value
+    fn is_valid(&self) -> bool {
+        let (name, value) = match self {
+            Entry::Line { line, colon } => match line.as_str().split_at(*colon) {
+                (_, "") => return false,
+                (name, value) => (name, &value[1..]),
+            },
+            Entry::Pair { name, value } => (name.as_str(), value.as_str()),
+        };
+        is_token(name) && is_field_text(value)
+    }
+
+    fn is(&self, name: &str) -> bool {
+        self.name().eq_ignore_ascii_case(name)
+    }
+
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.extend_from_slice(b"\r\n");
+        match self {
+            Entry::Line { line, .. } => buf.extend_from_slice(line.as_bytes()),
+            Entry::Pair { name, value } => {
+                buf.extend_from_slice(name.as_bytes());
+                buf.extend_from_slice(b": ");
+                buf.extend_from_slice(value.as_bytes());
+            }
+        }
+    }
+}
+
+/// Whether `s` can be written into the response head as is
+#[inline]
+pub(crate) fn is_field_text(s: &str) -> bool {
+    !s.bytes().any(|b| matches!(b, b'\r' | b'\n' | b'\0'))
+}
+
+/// The server's own header `name` stands for, `0` for any other
+fn reserved(name: &str) -> u8 {
+    if name.eq_ignore_ascii_case("content-length") {
+        CONTENT_LENGTH
+    } else if name.eq_ignore_ascii_case("date") {
+        DATE
+    } else if name.eq_ignore_ascii_case("server") {
+        SERVER
+    } else {
+        0
+    }
+}
+
+/// The headers of a [`Response`](crate::Response)
+///
+/// Names are matched case-insensitively and keep the case they were set
+/// with. Names and values are `&'static str` or owned strings, like the
+/// lines passed to [`Response::header`](crate::Response::header), so static
+/// headers are never copied before they are written. There is no limit on
+/// the number of headers, the first 16 are stored inside the response and
+/// more move to the heap.
+///
+/// The server writes `Server`, `Date` and `Content-Length` on its own. A
+/// `Server` or `Date` header set here replaces the default, a
+/// `Content-Length` is only used for a response to `HEAD`, which has no body
+/// to measure, and is dropped otherwise.
+///
+/// # Examples
+///
+/// ```
+/// # use may_minihttp::Response;
+/// # use bytes::BytesMut;
+/// # let mut buf = BytesMut::new();
+/// # let mut rsp = Response::_test_new(&mut buf);
+/// rsp.header("Content-Type: text/plain");
+/// rsp.headers_mut().append("Vary", "Accept");
+/// rsp.headers_mut().append("Vary", "Origin");
+///
+/// let headers = rsp.headers_mut();
+/// assert_eq!(headers.get("content-type"), Some("text/plain"));
+/// headers.insert("Content-Type", "application/json");
+/// assert_eq!(headers.get("Content-Type"), Some("application/json"));
+/// assert_eq!(headers.get_all("vary").collect::<Vec<_>>(), ["Accept", "Origin"]);
+/// assert!(headers.remove("Vary"));
+/// assert_eq!(headers.len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct ResponseHeaders {
+    entries: SmallVec<[Entry; INLINE_HEADERS]>,
+    // the server's own headers that are set, a bit set of `CONTENT_LENGTH`,
+    // `DATE` and `SERVER`
+    reserved: u8,
+}
+
+impl ResponseHeaders {
+    pub(crate) fn new() -> Self {
+        ResponseHeaders {
+            entries: SmallVec::new(),
+            reserved: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, entry: Entry) {
+        self.reserved |= reserved(entry.name());
+        self.entries.push(entry);
+    }
+
+    /// Append a whole `Name: value` line
+    #[inline]
+    pub(crate) fn append_line(&mut self, line: ResponseHeader) {
+        self.push(Entry::line(line));
+    }
+
+    /// Add a header, keeping the ones with the same name
+    pub fn append<N, V>(&mut self, name: N, value: V) -> &mut Self
+    where
+        N: IntoResponseHeader,
+        V: IntoResponseHeader,
+    {
+        self.push(Entry::Pair {
+            name: name.into_response_header(),
+            value: value.into_response_header(),
+        });
+        self
+    }
+
+    /// Set a header, replacing all the ones with the same name
+    ///
+    /// The header takes the place of the first one it replaces.
+    pub fn insert<N, V>(&mut self, name: N, value: V) -> &mut Self
+    where
+        N: IntoResponseHeader,
+        V: IntoResponseHeader,
+    {
+        let entry = Entry::Pair {
+            name: name.into_response_header(),
+            value: value.into_response_header(),
+        };
+        let name = entry.name();
+        match self.entries.iter().position(|e| e.is(name)) {
+            Some(i) => {
+                self.remove_after(i + 1, name);
+                self.entries[i] = entry;
+            }
+            None => self.push(entry),
+        }
+        self
+    }
+
+    /// The value of the first header named `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.iter().find(|e| e.is(name)).map(Entry::value)
+    }
+
+    /// The values of all the headers named `name`, in the order they were set
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries
+            .iter()
+            .filter(move |e| e.is(name))
+            .map(Entry::value)
+    }
+
+    /// Whether a header named `name` is set
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|e| e.is(name))
+    }
+
+    /// Remove all the headers named `name`, returns whether there were any
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        self.remove_after(0, name);
+        self.reserved &= !reserved(name);
+        self.entries.len() != len
+    }
+
+    /// The `(name, value)` pairs in the order they were set
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|e| (e.name(), e.value()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove the headers named `name` from `start` on
+    fn remove_after(&mut self, start: usize, name: &str) {
+        let mut i = 0;
+        self.entries.retain(|e| {
+            i += 1;
+            i <= start || !e.is(name)
+        });
+    }
+
+    /// Fail on the first header that would break the response head
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        match self.entries.iter().find(|e| !e.is_valid()) {
+            None => Ok(()),
+            Some(entry) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid response header {:?}", entry.name()),
+            )),
+        }
+    }
+
+    /// Whether `Server` or `Date` replace the server's own
+    #[inline]
+    pub(crate) fn overrides_head(&self) -> bool {
+        self.reserved & (DATE | SERVER) != 0
+    }
+
+    /// The `Content-Length` set by the service
+    #[inline]
+    pub(crate) fn content_length(&self) -> Option<&str> {
+        match self.reserved & CONTENT_LENGTH {
+            0 => None,
+            _ => self.get("content-length"),
+        }
+    }
+
+    /// Encode the headers, the server's own are left to the caller
+    pub(crate) fn encode(&self, buf: &mut BytesMut) {
+        if self.reserved == 0 {
+            for entry in &self.entries {
+                entry.encode(buf);
+            }
+        } else {
+            for entry in &self.entries {
+                if reserved(entry.name()) == 0 {
+                    entry.encode(buf);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! request router with path parameters and method dispatch
+
+use std::io;
+use std::mem;
+use std::sync::Arc;
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::StatusCode;
+use crate::uri;
+
+/// A route handler, shared by all the connections
+type Handler = Arc<dyn Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync>;
+
+/// Path parameters captured by the matched route
+///
+/// `:name` captures one path segment and `*name` the rest of the path, both
+/// are available under `name`. The values are percent-decoded after the path
+/// is matched, so an encoded `/` does not split a segment.
+#[derive(Debug, Default)]
+pub struct Params<'r> {
+    pairs: Vec<(&'r str, String)>,
+}
+
+impl Params<'_> {
+    /// The value captured for `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The captured `(name, value)` pairs in path order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (*n, v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// An [`HttpService`] that dispatches requests to handlers by path and method
+///
+/// Routes are stored in a radix tree, so a lookup walks the path once no
+/// matter how many routes are registered. A pattern is made of `/` separated
+/// segments:
+///
+/// - static segments match themselves, `/users`
+/// - `:name` matches any non-empty segment, `/users/:id`
+/// - `*name` matches the rest of the path, possibly empty, and must be the
+///   last segment, `/static/*file`
+///
+/// Static segments win over `:name`, which wins over `*name`. A path that
+/// matches a route without a handler for the request method is answered with
+/// `405 Method Not Allowed` and an `Allow` header, a path that matches no
+/// route with `404 Not Found` or the [`not_found`](Router::not_found)
+/// handler. `HEAD` requests are served by the `GET` handler unless a `HEAD`
+/// one is registered, the server leaves out the body.
+///
+/// Cloning a router is cheap, the routes are shared.
+///
+/// # Examples
+///
+/// ```no_run
+/// use may_minihttp::{HttpServer, Router};
+///
+/// let api = Router::new().get("/users/:id", |_req, rsp, params| {
+///     let id = params.get("id").unwrap_or_default();
+///     rsp.body_vec(format!("user {id}").into_bytes());
+///     Ok(())
+/// });
+///
+/// let router = Router::new()
+///     .get("/", |_req, rsp, _params| {
+///         rsp.body("Hello, world!");
+///         Ok(())
+///     })
+///     .mount("/api", api);
+///
+/// let server = HttpServer(router).start("127.0.0.1:8080").unwrap();
+/// server.wait();
+/// ```
+#[derive(Clone, Default)]
+pub struct Router {
+    table: Arc<Table>,
+}
+
+#[derive(Clone, Default)]
+struct Table {
+    tree: Node,
+    routes: Vec<Route>,
+    // not found handlers by the path prefix they apply to, the longest wins
+    fallbacks: Vec<(Box<str>, Handler)>,
+}
+
+#[derive(Clone)]
+struct Route {
+    pattern: Box<str>,
+    methods: Vec<(Box<str>, Handler)>,
+}
+
+impl Route {
+    /// The handler for `method`, `GET` handlers serve `HEAD` as well
+    #[inline]
+    fn handler(&self, method: &str) -> Option<&Handler> {
+        let find = |method: &str| {
+            self.methods
+                .iter()
+                .find(|(m, _)| **m == *method)
+                .map(|(_, h)| h)
+        };
+        find(method).or_else(|| (method == "HEAD").then(|| find("GET")).flatten())
+    }
+
+    /// The value of the `Allow` header
+    fn allow(&self) -> String {
+        let mut allow: Vec<&str> = self.methods.iter().map(|(m, _)| &**m).collect();
+        if allow.contains(&"GET") && !allow.contains(&"HEAD") {
+            allow.push("HEAD");
+        }
+        allow.join(", ")
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    /// Add a handler for `method` requests to `pattern`
+    ///
+    /// # Panics
+    ///
+    /// If the pattern does not start with `/`, has an empty parameter name,
+    /// a `*name` that is not the last segment, uses another parameter name
+    /// than an existing route at the same position or is already registered
+    /// for `method`.
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.add(method, pattern, Arc::new(handler));
+        self
+    }
+
+    /// Add a handler for `GET` requests to `pattern`
+    pub fn get<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    /// Add a handler for `POST` requests to `pattern`
+    pub fn post<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Add a handler for `PUT` requests to `pattern`
+    pub fn put<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.route("PUT", pattern, handler)
+    }
+
+    /// Add a handler for `PATCH` requests to `pattern`
+    pub fn patch<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.route("PATCH", pattern, handler)
+    }
+
+    /// Add a handler for `DELETE` requests to `pattern`
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.route("DELETE", pattern, handler)
+    }
+
+    /// Set the handler for requests that match no route
+    ///
+    /// The handler gets empty [`Params`] and a response with the `404` status
+    /// already set, it can change it.
+    pub fn not_found<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Request, &mut Response, &Params) -> io::Result<()> + Send + Sync + 'static,
+    {
+        let fallbacks = &mut Arc::make_mut(&mut self.table).fallbacks;
+        fallbacks.retain(|(prefix, _)| !prefix.is_empty());
+        fallbacks.push(("".into(), Arc::new(handler)));
+        self
+    }
+
+    /// Serve the routes of `router` under `prefix`
+    ///
+    /// `router`'s [`not_found`](Router::not_found) handler, if any, answers
+    /// the unmatched paths under `prefix`.
+    ///
+    /// # Panics
+    ///
+    /// If `prefix` is not empty and does not start with `/`, or a mounted
+    /// route conflicts with an existing one, see [`route`](Router::route).
+    pub fn mount(mut self, prefix: &str, router: Router) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        assert!(
+            prefix.is_empty() || prefix.starts_with('/'),
+            "mount prefix {prefix:?} must start with '/'"
+        );
+        for route in &router.table.routes {
+            let pattern = match &*route.pattern {
+                "/" if !prefix.is_empty() => prefix.to_owned(),
+                pattern => format!("{prefix}{pattern}"),
+            };
+            for (method, handler) in &route.methods {
+                self.add(method, &pattern, handler.clone());
+            }
+        }
+        let table = Arc::make_mut(&mut self.table);
+        for (sub, handler) in &router.table.fallbacks {
+            let sub = format!("{prefix}{sub}").into_boxed_str();
+            table.fallbacks.retain(|(prefix, _)| *prefix != sub);
+            table.fallbacks.push((sub, handler.clone()));
+        }
+        self
+    }
+
+    fn add(&mut self, method: &str, pattern: &str, handler: Handler) {
+        let pieces = parse_pattern(pattern);
+        let table = Arc::make_mut(&mut self.table);
+        let slot = table.tree.insert(&pieces);
+        let idx = *slot.get_or_insert_with(|| {
+            table.routes.push(Route {
+                pattern: pattern.into(),
+                methods: Vec::new(),
+            });
+            table.routes.len() - 1
+        });
+        let route = &mut table.routes[idx];
+        assert!(
+            route.methods.iter().all(|(m, _)| **m != *method),
+            "duplicate route {method} {pattern}"
+        );
+        route.methods.push((method.into(), handler));
+    }
+
+    /// Answer a request that matches no route
+    #[cold]
+    fn fallback(&self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let path = path_of(&req);
+        let handler = self
+            .table
+            .fallbacks
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(&**prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, handler)| handler.clone());
+        rsp.status(StatusCode::NOT_FOUND);
+        match handler {
+            Some(handler) => handler(req, rsp, &Params::default()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl HttpService for Router {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let table = &*self.table;
+        let mut captures = Vec::new();
+        let Some(idx) = table.tree.find(path_of(&req), 0, &mut captures) else {
+            return self.fallback(req, rsp);
+        };
+        let route = &table.routes[idx];
+        let Some(handler) = route.handler(req.method()) else {
+            rsp.status(StatusCode::METHOD_NOT_ALLOWED);
+            rsp.header(format!("Allow: {}", route.allow()));
+            return Ok(());
+        };
+        // the values borrow the request, which the handler takes
+        let params = Params {
+            pairs: captures
+                .into_iter()
+                .map(|(name, value)| (name, uri::percent_decode(value, false).into_owned()))
+                .collect(),
+        };
+        handler(req, rsp, &params)
+    }
+}
+
+/// The raw request path without the query string
+#[inline]
+fn path_of<'r>(req: &'r Request) -> &'r str {
+    uri::split_target(req.path()).0
+}
+
+enum Piece<'p> {
+    Static(String),
+    Param(&'p str),
+    Wildcard(&'p str),
+}
+
+/// Split a route pattern into static runs and parameters
+fn parse_pattern(pattern: &str) -> Vec<Piece<'_>> {
+    let Some(segments) = pattern.strip_prefix('/') else {
+        panic!("route pattern {pattern:?} must start with '/'");
+    };
+    let mut pieces = Vec::new();
+    let mut run = String::new();
+    let mut segments = segments.split('/').peekable();
+    while let Some(segment) = segments.next() {
+        run.push('/');
+        let piece = if let Some(name) = segment.strip_prefix(':') {
+            Piece::Param(name)
+        } else if let Some(name) = segment.strip_prefix('*') {
+            assert!(
+                segments.peek().is_none(),
+                "'*{name}' must be the last segment of {pattern:?}"
+            );
+            Piece::Wildcard(name)
+        } else {
+            run.push_str(segment);
+            continue;
+        };
+        if let Piece::Param(name) | Piece::Wildcard(name) = piece {
+            assert!(!name.is_empty(), "unnamed parameter in {pattern:?}");
+        }
+        pieces.push(Piece::Static(mem::take(&mut run)));
+        pieces.push(piece);
+    }
+    if !run.is_empty() {
+        pieces.push(Piece::Static(run));
+    }
+    pieces
+}
+
+/// A radix tree node, its prefix is matched before its children
+#[derive(Clone, Default)]
+struct Node {
+    // static bytes matched by this node, may end inside a utf-8 sequence
+    prefix: Vec<u8>,
+    // first byte of each static child
+    indices: Vec<u8>,
+    children: Vec<Node>,
+    // `:name` child, matches one non-empty segment
+    param: Option<Box<(Box<str>, Node)>>,
+    // `*name` tail and its route
+    wildcard: Option<(Box<str>, Option<usize>)>,
+    // route that ends at this node
+    route: Option<usize>,
+}
+
+impl Node {
+    /// Find the route slot for `pieces`, creating the nodes on the way
+    fn insert(&mut self, pieces: &[Piece]) -> &mut Option<usize> {
+        match pieces.split_first() {
+            None => &mut self.route,
+            Some((Piece::Static(s), rest)) => self.static_child(s.as_bytes()).insert(rest),
+            Some((Piece::Param(name), rest)) => {
+                let param = self
+                    .param
+                    .get_or_insert_with(|| Box::new(((*name).into(), Node::default())));
+                assert!(
+                    *param.0 == **name,
+                    "parameter ':{name}' conflicts with ':{}'",
+                    param.0
+                );
+                param.1.insert(rest)
+            }
+            Some((Piece::Wildcard(name), _)) => {
+                let wildcard = self.wildcard.get_or_insert_with(|| ((*name).into(), None));
+                assert!(
+                    *wildcard.0 == **name,
+                    "parameter '*{name}' conflicts with '*{}'",
+                    wildcard.0
+                );
+                &mut wildcard.1
+            }
+        }
+    }
+
+    /// The node reached by matching `path` after this one, split or created
+    fn static_child(&mut self, path: &[u8]) -> &mut Node {
+        let Some(&first) = path.first() else {
+            return self;
+        };
+        let Some(i) = self.indices.iter().position(|&b| b == first) else {
+            self.indices.push(first);
+            self.children.push(Node {
+                prefix: path.to_vec(),
+                ..Node::default()
+            });
+            return self.children.last_mut().unwrap();
+        };
+        let child = &mut self.children[i];
+        let common = child
+            .prefix
+            .iter()
+            .zip(path)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common < child.prefix.len() {
+            child.split(common);
+        }
+        child.static_child(&path[common..])
+    }
+
+    /// Move everything after `at` bytes of the prefix into a new child
+    fn split(&mut self, at: usize) {
+        let suffix = self.prefix.split_off(at);
+        let prefix = mem::take(&mut self.prefix);
+        let tail = Node {
+            prefix: suffix,
+            ..mem::take(self)
+        };
+        *self = Node {
+            prefix,
+            indices: vec![tail.prefix[0]],
+            children: vec![tail],
+            ..Node::default()
+        };
+    }
+
+    /// Find the route for `path[pos..]`, collecting the parameters
+    ///
+    /// Backtracks from static children to `:name` to `*name`.
+    fn find<'n, 'p>(
+        &'n self,
+        path: &'p str,
+        pos: usize,
+        captures: &mut Vec<(&'n str, &'p str)>,
+    ) -> Option<usize> {
+        let rest = &path.as_bytes()[pos..];
+        match rest.first() {
+            None if self.route.is_some() => return self.route,
+            None => {}
+            Some(&first) => {
+                if let Some(i) = self.indices.iter().position(|&b| b == first) {
+                    let child = &self.children[i];
+                    if rest.starts_with(&child.prefix) {
+                        let found = child.find(path, pos + child.prefix.len(), captures);
+                        if found.is_some() {
+                            return found;
+                        }
+                    }
+                }
+            }
+        }
+        // parameters always follow a '/', so `pos` is on a char boundary
+        if let Some((name, node)) = self.param.as_deref() {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'/')
+                .map_or(path.len(), |i| pos + i);
+            if end > pos {
+                let len = captures.len();
+                captures.push((name, &path[pos..end]));
+                let found = node.find(path, end, captures);
+                if found.is_some() {
+                    return found;
+                }
+                captures.truncate(len);
+            }
+        }
+        if let Some((name, Some(route))) = &self.wildcard {
+            captures.push((name, &path[pos..]));
+            return Some(*route);
+        }
+        None
+    }
+}
@@ -1,16 +1,26 @@
 use crate::config::HttpConfig;
-use crate::http_server::HttpServiceFactory;
+use crate::error::{DefaultErrorHandler, ErrorHandler};
+use crate::forwarded::{ForwardedHeaders, IpNet, TrustedProxies};
+use crate::http_server::{self, HttpServiceFactory};
+use crate::listener::Listener;
+#[cfg(unix)]
+use crate::listener::UnixSocket;
 use crate::request::MaxHeaders;
-use may::coroutine;
+use crate::server_handle::ServerHandle;
+use may::net::TcpListener;
 use std::io;
 use std::net::ToSocketAddrs;
+use std::sync::Arc;
 
 /// Builder for creating and configuring HTTP servers
 ///
+/// The configuration is applied at runtime, so the header limit and buffer
+/// sizes can come from a config file instead of a const generic parameter.
+///
 /// # Examples
 ///
 /// ```no_run
-/// use may_minihttp::{HttpServer, HttpService, Request, Response, MaxHeaders};
+/// use may_minihttp::{HttpServer, HttpService, Request, Response, MaxHeaders, ServerBuilder};
 /// use std::io;
 ///
 /// #[derive(Clone)]
@@ -24,42 +34,121 @@ use std::net::ToSocketAddrs;
 /// }
 ///
 /// // Start server with custom MaxHeaders
-/// let server = HttpServer::new(MyService)
+/// let server = ServerBuilder::new(HttpServer(MyService))
 ///     .max_headers(MaxHeaders::Large)
 ///     .bind("127.0.0.1:8080")
 ///     .unwrap();
 /// ```
-pub struct HttpServer<F> {
+pub struct ServerBuilder<F> {
     factory: F,
     config: HttpConfig,
+    error_handler: Arc<dyn ErrorHandler>,
+    trusted_proxies: TrustedProxies,
 }
 
-impl<F: HttpServiceFactory> HttpServer<F> {
+impl<F: HttpServiceFactory> ServerBuilder<F> {
     /// Create a new HTTP server with the given service factory
     pub fn new(factory: F) -> Self {
         Self {
             factory,
             config: HttpConfig::default(),
+            error_handler: Arc::new(DefaultErrorHandler),
+            trusted_proxies: TrustedProxies::default(),
         }
     }
-    
+
     /// Set the maximum number of headers to accept
     pub fn max_headers(mut self, max_headers: MaxHeaders) -> Self {
         self.config.max_headers = max_headers;
         self
     }
-    
+
     /// Set the full HTTP configuration
     pub fn config(mut self, config: HttpConfig) -> Self {
         self.config = config;
         self
     }
-    
+
+    /// Set the hook that customizes the responses the server writes on its own
+    pub fn error_handler<H: ErrorHandler>(mut self, handler: H) -> Self {
+        self.error_handler = Arc::new(handler);
+        self
+    }
+
+    /// Trust the forwarding headers of requests from these proxies
+    ///
+    /// [`Request::client_addr`](crate::Request::client_addr),
+    /// [`Request::scheme`](crate::Request::scheme) and
+    /// [`Request::host`](crate::Request::host) then follow the
+    /// [`forwarded_headers`](Self::forwarded_headers) through the listed
+    /// proxies back to the client. The headers of any other peer are
+    /// ignored, as it could send anything.
+    ///
+    /// ```no_run
+    /// # use may_minihttp::{HttpServer, HttpService, IpNet, Request, Response, ServerBuilder};
+    /// # #[derive(Clone)]
+    /// # struct Hello;
+    /// # impl HttpService for Hello {
+    /// #     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// let proxies = ["10.0.0.0/8", "fd00::/8"].map(|net| net.parse::<IpNet>().unwrap());
+    /// let server = ServerBuilder::new(HttpServer(Hello))
+    ///     .trusted_proxies(proxies)
+    ///     .bind("0.0.0.0:8080")
+    ///     .unwrap();
+    /// ```
+    pub fn trusted_proxies<I: IntoIterator<Item = IpNet>>(mut self, proxies: I) -> Self {
+        self.trusted_proxies.nets = proxies.into_iter().collect();
+        self
+    }
+
+    /// Set the forwarding headers the trusted proxies set, the
+    /// `X-Forwarded-*` ones by default
+    ///
+    /// The other family is ignored, the proxies pass it on from the client.
+    pub fn forwarded_headers(mut self, headers: ForwardedHeaders) -> Self {
+        self.trusted_proxies.headers = headers;
+        self
+    }
+
+    /// Trust the forwarding headers of requests arriving on a Unix socket
+    ///
+    /// A peer on a Unix socket has no address to check against
+    /// [`trusted_proxies`](Self::trusted_proxies), turn this on when only
+    /// the reverse proxy can connect to the socket, see
+    /// [`UnixSocket::mode`]. The chain is then followed from that peer like
+    /// from a trusted proxy.
+    #[cfg(unix)]
+    pub fn trust_unix_peers(mut self, trust: bool) -> Self {
+        self.trusted_proxies.unix_peers = trust;
+        self
+    }
+
     /// Bind to the given address and start the server
-    pub fn bind<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
-        // For now, we'll just use the factory's start method
-        // TODO: Pass config through to control header limits
-        self.factory.start(addr)
+    /// return a [`ServerHandle`] that you can use to stop the service
+    pub fn bind<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
+        let listener = TcpListener::bind(addr)?;
+        self.serve(Listener::Tcp(listener))
+    }
+
+    /// Bind to a Unix domain socket and start the server
+    ///
+    /// The socket file is removed again by [`ServerHandle::shutdown`].
+    #[cfg(unix)]
+    pub fn bind_unix(self, socket: UnixSocket) -> io::Result<ServerHandle> {
+        let listener = socket.bind()?;
+        self.serve(Listener::Unix(listener, socket))
     }
-}
 
+    fn serve(self, listener: Listener) -> io::Result<ServerHandle> {
+        http_server::serve(
+            listener,
+            self.factory,
+            self.config,
+            self.error_handler,
+            Arc::new(self.trusted_proxies),
+        )
+    }
+}
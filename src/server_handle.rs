@@ -0,0 +1,357 @@
+//! handle of a running server, used to stop it gracefully
+
+use std::collections::HashMap;
+use std::mem::ManuallyDrop;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use may::coroutine;
+use may::net::TcpStream;
+
+#[cfg(unix)]
+use crate::listener::UnixSocket;
+use crate::listener::{BoundAddr, Stream};
+
+// connection states, only `IDLE` connections can be closed at any time
+const BUSY: u8 = 0;
+const IDLE: u8 = 1;
+const CLOSING: u8 = 2;
+
+/// Handle of a running http server
+///
+/// Returned by [`ServerBuilder::bind`](crate::ServerBuilder::bind) and the
+/// various `start` methods. Dropping the handle leaves the server running;
+/// use [`shutdown`](Self::shutdown) to stop it and drain the connections.
+///
+/// # Examples
+///
+/// ```no_run
+/// use may_minihttp::{HttpServer, HttpService, Request, Response};
+/// use std::io;
+/// use std::time::Duration;
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+///         rsp.body("Hello, world!");
+///         Ok(())
+///     }
+/// }
+///
+/// let server = HttpServer(Hello).start("127.0.0.1:8080").unwrap();
+/// // ... on SIGTERM
+/// if !server.shutdown(Duration::from_secs(30)) {
+///     eprintln!("some connections were force closed");
+/// }
+/// ```
+pub struct ServerHandle {
+    accept: coroutine::JoinHandle<()>,
+    state: Arc<ServerState>,
+    addr: BoundAddr,
+}
+
+impl ServerHandle {
+    pub(crate) fn new(
+        accept: coroutine::JoinHandle<()>,
+        state: Arc<ServerState>,
+        addr: BoundAddr,
+    ) -> Self {
+        ServerHandle {
+            accept,
+            state,
+            addr,
+        }
+    }
+
+    /// Gracefully stop the server
+    ///
+    /// Stops accepting new connections, closes the idle keep-alive ones and
+    /// lets every busy connection finish the pipelined requests it has
+    /// already received; the last response on such a connection carries
+    /// `Connection: close`. Connections still open after `timeout` are force
+    /// closed.
+    ///
+    /// Returns `true` if all the connections were drained in time.
+    pub fn shutdown(self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        self.state.begin_shutdown();
+
+        // the accept loop is parked in `accept`, connect once to wake it up
+        // so that it can observe the flag and drop the listener
+        match &self.addr {
+            BoundAddr::Tcp(addr) => drop(TcpStream::connect(wake_addr(*addr))),
+            #[cfg(unix)]
+            BoundAddr::Unix(socket) => drop(socket.connect()),
+        }
+        self.accept.join().ok();
+        #[cfg(unix)]
+        if let BoundAddr::Unix(socket) = &self.addr {
+            socket.unlink();
+        }
+
+        while self.state.active_connections() > 0 {
+            if Instant::now() >= deadline {
+                self.state.force_close();
+                return false;
+            }
+            coroutine::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    /// The local address the server is bound to
+    ///
+    /// When binding to port `0` this reports the port assigned by the OS. A
+    /// server on a Unix socket reports the unspecified address `0.0.0.0:0`,
+    /// see [`unix_socket`](Self::unix_socket).
+    ///
+    /// ```no_run
+    /// # use may_minihttp::{HttpServer, HttpService, Request, Response};
+    /// # #[derive(Clone)]
+    /// # struct Hello;
+    /// # impl HttpService for Hello {
+    /// #     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// let server = HttpServer(Hello).start("127.0.0.1:0").unwrap();
+    /// let port = server.local_addr().port();
+    /// assert_ne!(port, 0);
+    /// ```
+    #[inline]
+    pub fn local_addr(&self) -> SocketAddr {
+        match &self.addr {
+            BoundAddr::Tcp(addr) => *addr,
+            #[cfg(unix)]
+            BoundAddr::Unix(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        }
+    }
+
+    /// The Unix socket the server listens on, `None` for a TCP server
+    #[cfg(unix)]
+    pub fn unix_socket(&self) -> Option<&UnixSocket> {
+        match &self.addr {
+            BoundAddr::Tcp(_) => None,
+            BoundAddr::Unix(socket) => Some(socket),
+        }
+    }
+
+    /// Snapshot of the server counters
+    pub fn stats(&self) -> ServerStats {
+        let counters = &self.state.counters;
+        ServerStats {
+            connections_accepted: self.state.next_id.load(Ordering::Relaxed) as u64,
+            active_connections: self.state.active_connections() as u64,
+            keep_alive_timeouts: counters.keep_alive_timeouts.load(Ordering::Relaxed),
+            header_read_timeouts: counters.header_read_timeouts.load(Ordering::Relaxed),
+            body_read_timeouts: counters.body_read_timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Block until the accept loop exits
+    pub fn wait(&self) {
+        self.accept.wait();
+    }
+
+    /// Wait for the accept loop to exit
+    pub fn join(self) -> std::thread::Result<()> {
+        self.accept.join()
+    }
+}
+
+/// Snapshot of the server counters, see [`ServerHandle::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Connections accepted since the server started
+    pub connections_accepted: u64,
+    /// Connections currently open
+    pub active_connections: u64,
+    /// Idle connections closed by the keep-alive timeout
+    pub keep_alive_timeouts: u64,
+    /// Connections closed with `408` because the request head was not
+    /// received within the header read timeout
+    pub header_read_timeouts: u64,
+    /// Request body reads that exceeded the body read timeout
+    pub body_read_timeouts: u64,
+}
+
+/// Event counters shared by all the connections of a server
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) keep_alive_timeouts: AtomicU64,
+    pub(crate) header_read_timeouts: AtomicU64,
+    pub(crate) body_read_timeouts: AtomicU64,
+}
+
+impl Counters {
+    #[inline]
+    pub(crate) fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// connect target used to wake the accept loop
+fn wake_addr(mut addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+        IpAddr::V6(ip) if ip.is_unspecified() => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+        _ => {}
+    }
+    addr
+}
+
+/// Shut down a socket from outside its connection without closing it
+fn shutdown_socket(socket: usize) {
+    #[cfg(unix)]
+    use std::os::fd::FromRawFd;
+    #[cfg(windows)]
+    use std::os::windows::io::FromRawSocket;
+    // safety: a registered socket stays open until its guard is dropped, and
+    // the borrowed socket is never closed
+    #[cfg(unix)]
+    let socket = unsafe { std::net::TcpStream::from_raw_fd(socket as _) };
+    #[cfg(windows)]
+    let socket = unsafe { std::net::TcpStream::from_raw_socket(socket as _) };
+    // `shutdown` works the same on a Unix socket
+    ManuallyDrop::new(socket).shutdown(Shutdown::Both).ok();
+}
+
+// number of connection registry shards, connections are spread over them
+// by id so that they rarely contend for the same lock
+const SHARDS: usize = 32;
+
+struct ConnEntry {
+    // the raw socket of the connection, only used to shut it down
+    socket: usize,
+    // shared with the connection, which flips it without taking the lock
+    state: Arc<AtomicU8>,
+}
+
+/// State shared by the accept loop, the connections and the [`ServerHandle`]
+#[derive(Default)]
+pub(crate) struct ServerState {
+    shutting_down: AtomicBool,
+    next_id: AtomicUsize,
+    active: AtomicUsize,
+    conns: [Mutex<HashMap<usize, ConnEntry>>; SHARDS],
+    counters: Counters,
+}
+
+impl ServerState {
+    #[inline]
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// Track a newly accepted connection until the returned guard is dropped
+    ///
+    /// The guard must be dropped before the stream is closed, the registry
+    /// only keeps the raw socket.
+    pub(crate) fn register(self: &Arc<Self>, stream: &Stream) -> ConnGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let state = Arc::new(AtomicU8::new(BUSY));
+        let entry = ConnEntry {
+            socket: stream.id(),
+            state: state.clone(),
+        };
+        self.active.fetch_add(1, Ordering::SeqCst);
+        self.shard(id).insert(id, entry);
+        ConnGuard {
+            server: self.clone(),
+            id,
+            state,
+        }
+    }
+
+    #[inline]
+    fn shard(&self, id: usize) -> MutexGuard<'_, HashMap<usize, ConnEntry>> {
+        self.conns[id % SHARDS]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn active_connections(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        for shard in 0..SHARDS {
+            for conn in self.shard(shard).values() {
+                let closing =
+                    conn.state
+                        .compare_exchange(IDLE, CLOSING, Ordering::SeqCst, Ordering::SeqCst);
+                if closing.is_ok() {
+                    shutdown_socket(conn.socket);
+                }
+            }
+        }
+    }
+
+    fn force_close(&self) {
+        for shard in 0..SHARDS {
+            for conn in self.shard(shard).values() {
+                conn.state.store(CLOSING, Ordering::SeqCst);
+                shutdown_socket(conn.socket);
+            }
+        }
+    }
+}
+
+/// Registration of a live connection, removed from the server on drop
+pub(crate) struct ConnGuard {
+    server: Arc<ServerState>,
+    id: usize,
+    state: Arc<AtomicU8>,
+}
+
+impl ConnGuard {
+    /// Number of the connection in accept order
+    #[inline]
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The server is shutting down, the connection should be closed once the
+    /// requests already received are served
+    #[inline]
+    pub(crate) fn is_draining(&self) -> bool {
+        self.server.is_shutting_down()
+    }
+
+    #[inline]
+    pub(crate) fn counters(&self) -> &Counters {
+        &self.server.counters
+    }
+
+    /// Mark the connection idle before waiting for the next request
+    ///
+    /// Returns `false` if the server is shutting down and the connection
+    /// should be closed instead of waiting.
+    #[inline]
+    pub(crate) fn enter_idle(&self) -> bool {
+        self.state.store(IDLE, Ordering::SeqCst);
+        !self.server.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Mark the connection busy again after new data arrived
+    ///
+    /// Returns `false` if the server already closed the idle connection.
+    #[inline]
+    pub(crate) fn leave_idle(&self) -> bool {
+        self.state
+            .compare_exchange(IDLE, BUSY, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        self.server.shard(self.id).remove(&self.id);
+        self.server.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
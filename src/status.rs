@@ -0,0 +1,306 @@
+//! response status codes and their reason phrases
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An HTTP response status code
+///
+/// Any three digit code from 100 to 999 is valid. The registered codes have
+/// constants and a canonical reason phrase, and their status lines are
+/// precomputed so they are written with a single copy.
+///
+/// # Examples
+///
+/// ```
+/// use may_minihttp::StatusCode;
+///
+/// assert_eq!(StatusCode::NOT_FOUND.as_u16(), 404);
+/// assert_eq!(StatusCode::NOT_FOUND.canonical_reason(), Some("Not Found"));
+/// assert!(StatusCode::NOT_FOUND.is_client_error());
+///
+/// let code = StatusCode::from_u16(599).unwrap();
+/// assert!(code.is_server_error());
+/// assert_eq!(code.canonical_reason(), None);
+/// assert!(StatusCode::from_u16(1000).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u16);
+
+/// A status code outside of 100 to 999
+///
+/// Converts into an [`io::ErrorKind::InvalidInput`] error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidStatusCode {
+    code: u16,
+}
+
+impl InvalidStatusCode {
+    /// The rejected code
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+}
+
+impl fmt::Display for InvalidStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid status code {}", self.code)
+    }
+}
+
+impl Error for InvalidStatusCode {}
+
+impl From<InvalidStatusCode> for io::Error {
+    fn from(e: InvalidStatusCode) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+impl StatusCode {
+    /// The status code `code`, fails unless it is within 100 to 999
+    pub const fn from_u16(code: u16) -> Result<StatusCode, InvalidStatusCode> {
+        match code {
+            100..=999 => Ok(StatusCode(code)),
+            _ => Err(InvalidStatusCode { code }),
+        }
+    }
+
+    /// The code as a number
+    #[inline]
+    pub const fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// The registered reason phrase, `None` for an unregistered code
+    #[inline]
+    pub fn canonical_reason(&self) -> Option<&'static str> {
+        canonical(self.0).map(|(reason, _)| reason)
+    }
+
+    /// `1xx`, the request was received and is being processed
+    #[inline]
+    pub const fn is_informational(&self) -> bool {
+        matches!(self.0, 100..=199)
+    }
+
+    /// `2xx`, the request was handled
+    #[inline]
+    pub const fn is_success(&self) -> bool {
+        matches!(self.0, 200..=299)
+    }
+
+    /// `3xx`, the client has to take further action
+    #[inline]
+    pub const fn is_redirection(&self) -> bool {
+        matches!(self.0, 300..=399)
+    }
+
+    /// `4xx`, the request is at fault
+    #[inline]
+    pub const fn is_client_error(&self) -> bool {
+        matches!(self.0, 400..=499)
+    }
+
+    /// `5xx`, the server failed to handle a valid request
+    #[inline]
+    pub const fn is_server_error(&self) -> bool {
+        matches!(self.0, 500..=599)
+    }
+
+    /// The `HTTP/1.1` status line with the canonical reason phrase, followed
+    /// by the `Server` header and the name of the `Date` header
+    #[inline]
+    pub(crate) fn status_line(&self) -> Option<&'static [u8]> {
+        canonical(self.0).map(|(_, line)| line)
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        StatusCode::OK
+    }
+}
+
+impl TryFrom<u16> for StatusCode {
+    type Error = InvalidStatusCode;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        StatusCode::from_u16(code)
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+impl PartialEq<u16> for StatusCode {
+    fn eq(&self, other: &u16) -> bool {
+        self.0 == *other
+    }
+}
+
+/// The code followed by the canonical reason phrase, if there is one
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {reason}", self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+macro_rules! status_codes {
+    ($($(#[$doc:meta])* ($code:literal, $name:ident, $reason:literal);)+) => {
+        impl StatusCode {
+            $(
+                $(#[$doc])*
+                pub const $name: StatusCode = StatusCode($code);
+            )+
+        }
+
+        /// The reason phrase and the precomputed status line of a registered code
+        fn canonical(code: u16) -> Option<(&'static str, &'static [u8])> {
+            match code {
+                $(
+                    $code => Some((
+                        $reason,
+                        concat!("HTTP/1.1 ", $code, " ", $reason, "\r\nServer: M\r\nDate: ")
+                            .as_bytes(),
+                    )),
+                )+
+                _ => None,
+            }
+        }
+    };
+}
+
+status_codes! {
+    /// `100 Continue`
+    (100, CONTINUE, "Continue");
+    /// `101 Switching Protocols`
+    (101, SWITCHING_PROTOCOLS, "Switching Protocols");
+    /// `102 Processing`
+    (102, PROCESSING, "Processing");
+    /// `103 Early Hints`
+    (103, EARLY_HINTS, "Early Hints");
+
+    /// `200 OK`
+    (200, OK, "OK");
+    /// `201 Created`
+    (201, CREATED, "Created");
+    /// `202 Accepted`
+    (202, ACCEPTED, "Accepted");
+    /// `203 Non-Authoritative Information`
+    (203, NON_AUTHORITATIVE_INFORMATION, "Non-Authoritative Information");
+    /// `204 No Content`
+    (204, NO_CONTENT, "No Content");
+    /// `205 Reset Content`
+    (205, RESET_CONTENT, "Reset Content");
+    /// `206 Partial Content`
+    (206, PARTIAL_CONTENT, "Partial Content");
+    /// `207 Multi-Status`
+    (207, MULTI_STATUS, "Multi-Status");
+    /// `208 Already Reported`
+    (208, ALREADY_REPORTED, "Already Reported");
+    /// `226 IM Used`
+    (226, IM_USED, "IM Used");
+
+    /// `300 Multiple Choices`
+    (300, MULTIPLE_CHOICES, "Multiple Choices");
+    /// `301 Moved Permanently`
+    (301, MOVED_PERMANENTLY, "Moved Permanently");
+    /// `302 Found`
+    (302, FOUND, "Found");
+    /// `303 See Other`
+    (303, SEE_OTHER, "See Other");
+    /// `304 Not Modified`
+    (304, NOT_MODIFIED, "Not Modified");
+    /// `305 Use Proxy`
+    (305, USE_PROXY, "Use Proxy");
+    /// `307 Temporary Redirect`
+    (307, TEMPORARY_REDIRECT, "Temporary Redirect");
+    /// `308 Permanent Redirect`
+    (308, PERMANENT_REDIRECT, "Permanent Redirect");
+
+    /// `400 Bad Request`
+    (400, BAD_REQUEST, "Bad Request");
+    /// `401 Unauthorized`
+    (401, UNAUTHORIZED, "Unauthorized");
+    /// `402 Payment Required`
+    (402, PAYMENT_REQUIRED, "Payment Required");
+    /// `403 Forbidden`
+    (403, FORBIDDEN, "Forbidden");
+    /// `404 Not Found`
+    (404, NOT_FOUND, "Not Found");
+    /// `405 Method Not Allowed`
+    (405, METHOD_NOT_ALLOWED, "Method Not Allowed");
+    /// `406 Not Acceptable`
+    (406, NOT_ACCEPTABLE, "Not Acceptable");
+    /// `407 Proxy Authentication Required`
+    (407, PROXY_AUTHENTICATION_REQUIRED, "Proxy Authentication Required");
+    /// `408 Request Timeout`
+    (408, REQUEST_TIMEOUT, "Request Timeout");
+    /// `409 Conflict`
+    (409, CONFLICT, "Conflict");
+    /// `410 Gone`
+    (410, GONE, "Gone");
+    /// `411 Length Required`
+    (411, LENGTH_REQUIRED, "Length Required");
+    /// `412 Precondition Failed`
+    (412, PRECONDITION_FAILED, "Precondition Failed");
+    /// `413 Payload Too Large`, "Content Too Large" in RFC 9110
+    (413, PAYLOAD_TOO_LARGE, "Payload Too Large");
+    /// `414 URI Too Long`
+    (414, URI_TOO_LONG, "URI Too Long");
+    /// `415 Unsupported Media Type`
+    (415, UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type");
+    /// `416 Range Not Satisfiable`
+    (416, RANGE_NOT_SATISFIABLE, "Range Not Satisfiable");
+    /// `417 Expectation Failed`
+    (417, EXPECTATION_FAILED, "Expectation Failed");
+    /// `421 Misdirected Request`
+    (421, MISDIRECTED_REQUEST, "Misdirected Request");
+    /// `422 Unprocessable Entity`, "Unprocessable Content" in RFC 9110
+    (422, UNPROCESSABLE_ENTITY, "Unprocessable Entity");
+    /// `423 Locked`
+    (423, LOCKED, "Locked");
+    /// `424 Failed Dependency`
+    (424, FAILED_DEPENDENCY, "Failed Dependency");
+    /// `425 Too Early`
+    (425, TOO_EARLY, "Too Early");
+    /// `426 Upgrade Required`
+    (426, UPGRADE_REQUIRED, "Upgrade Required");
+    /// `428 Precondition Required`
+    (428, PRECONDITION_REQUIRED, "Precondition Required");
+    /// `429 Too Many Requests`
+    (429, TOO_MANY_REQUESTS, "Too Many Requests");
+    /// `431 Request Header Fields Too Large`
+    (431, REQUEST_HEADER_FIELDS_TOO_LARGE, "Request Header Fields Too Large");
+    /// `451 Unavailable For Legal Reasons`
+    (451, UNAVAILABLE_FOR_LEGAL_REASONS, "Unavailable For Legal Reasons");
+
+    /// `500 Internal Server Error`
+    (500, INTERNAL_SERVER_ERROR, "Internal Server Error");
+    /// `501 Not Implemented`
+    (501, NOT_IMPLEMENTED, "Not Implemented");
+    /// `502 Bad Gateway`
+    (502, BAD_GATEWAY, "Bad Gateway");
+    /// `503 Service Unavailable`
+    (503, SERVICE_UNAVAILABLE, "Service Unavailable");
+    /// `504 Gateway Timeout`
+    (504, GATEWAY_TIMEOUT, "Gateway Timeout");
+    /// `505 HTTP Version Not Supported`
+    (505, HTTP_VERSION_NOT_SUPPORTED, "HTTP Version Not Supported");
+    /// `506 Variant Also Negotiates`
+    (506, VARIANT_ALSO_NEGOTIATES, "Variant Also Negotiates");
+    /// `507 Insufficient Storage`
+    (507, INSUFFICIENT_STORAGE, "Insufficient Storage");
+    /// `508 Loop Detected`
+    (508, LOOP_DETECTED, "Loop Detected");
+    /// `510 Not Extended`
+    (510, NOT_EXTENDED, "Not Extended");
+    /// `511 Network Authentication Required`
+    (511, NETWORK_AUTHENTICATION_REQUIRED, "Network Authentication Required");
+}
@@ -0,0 +1,140 @@
+//! request target splitting and percent-decoding
+
+use std::borrow::Cow;
+
+/// Split a request target into its path and query
+///
+/// The absolute form `http://host/path?query` is reduced to the path, the
+/// asterisk form `*` is returned as is.
+pub(crate) fn split_target(target: &str) -> (&str, Option<&str>) {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+    let path = match path.split_once("://") {
+        Some((_, rest)) if !path.starts_with('/') => rest.find('/').map_or("/", |i| &rest[i..]),
+        _ => path,
+    };
+    (path, query)
+}
+
+/// Decode `%XX` escapes, and `+` as a space if `plus` is set
+///
+/// Borrows `s` when there is nothing to decode. Malformed escapes are kept
+/// as they are and invalid UTF-8 is replaced with `U+FFFD`.
+pub(crate) fn percent_decode(s: &str, plus: bool) -> Cow<'_, str> {
+    let bytes = s.as_bytes();
+    let Some(first) = bytes.iter().position(|&b| b == b'%' || (plus && b == b'+')) else {
+        return Cow::Borrowed(s);
+    };
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[..first]);
+    let mut i = first;
+    while let Some(&b) = bytes.get(i) {
+        i += 1;
+        match b {
+            b'+' if plus => out.push(b' '),
+            b'%' => match (hex(bytes.get(i)), hex(bytes.get(i + 1))) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+    }
+    match String::from_utf8(out) {
+        Ok(s) => Cow::Owned(s),
+        Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+    }
+}
+
+#[inline]
+fn hex(b: Option<&u8>) -> Option<u8> {
+    let b = *b?;
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Iterator over the decoded `key=value` pairs of a query string
+///
+/// Pairs are separated by `&`, a pair without `=` has an empty value and
+/// empty pairs are skipped. Keys and values are percent-decoded with `+` as
+/// a space, they borrow the query unless they had to be decoded. Repeated
+/// keys are returned in order.
+///
+/// The same format is used by `application/x-www-form-urlencoded` bodies.
+///
+/// # Examples
+///
+/// ```
+/// use may_minihttp::QueryPairs;
+///
+/// let pairs: Vec<_> = QueryPairs::new("tag=a&tag=b+c&q=%C3%A4").collect();
+/// assert_eq!(pairs[0], ("tag".into(), "a".into()));
+/// assert_eq!(pairs[1], ("tag".into(), "b c".into()));
+/// assert_eq!(pairs[2], ("q".into(), "ä".into()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryPairs<'a> {
+    rest: &'a str,
+}
+
+impl<'a> QueryPairs<'a> {
+    /// Iterate the pairs of `query`, which must not include the leading `?`
+    pub fn new(query: &'a str) -> Self {
+        QueryPairs { rest: query }
+    }
+}
+
+impl<'a> Iterator for QueryPairs<'a> {
+    type Item = (Cow<'a, str>, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.rest.is_empty() {
+            let (pair, rest) = self.rest.split_once('&').unwrap_or((self.rest, ""));
+            self.rest = rest;
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            return Some((percent_decode(key, true), percent_decode(value, true)));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nothing to decode borrows the input, `+` only counts with `plus`
+    #[test]
+    fn percent_decode_borrows_plain_input() {
+        assert!(matches!(
+            percent_decode("/a/b", false),
+            Cow::Borrowed("/a/b")
+        ));
+        assert!(matches!(percent_decode("a+b", false), Cow::Borrowed("a+b")));
+    }
+
+    #[test]
+    fn percent_decode_escapes() {
+        assert_eq!(percent_decode("%2Fa%20b", false), "/a b");
+        assert_eq!(percent_decode("a+b%2B", true), "a b+");
+        assert_eq!(percent_decode("%c3%A4", false), "ä");
+    }
+
+    /// Malformed escapes are kept, invalid UTF-8 is replaced
+    #[test]
+    fn percent_decode_malformed_input() {
+        assert_eq!(percent_decode("100%", false), "100%");
+        assert_eq!(percent_decode("%zz%4", false), "%zz%4");
+        assert_eq!(percent_decode("a%FFb", false), "a\u{FFFD}b");
+    }
+}
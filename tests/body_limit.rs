@@ -0,0 +1,154 @@
+//! Tests for the request body size limit
+//!
+//! These tests verify that:
+//! 1. A `Content-Length` over `max_body_size` gets `413 Payload Too Large`
+//!    on the first body read, before the body is sent
+//! 2. A chunked body crossing the limit gets `413 Payload Too Large`
+//! 3. `BodyReader::limit` replaces the server limit for one request
+//! 4. A large body the service does not read closes the connection, a small
+//!    one is skipped and the connection is kept
+
+mod common;
+
+use common::{connect, read_to_close, read_until, start_server};
+use may_minihttp::{
+    HttpConfig, HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle,
+};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// Echoes the body, `/upload` allows 64 bytes, `/ignore` never reads it
+#[derive(Clone)]
+struct LimitService;
+
+impl HttpService for LimitService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        let mut data = Vec::new();
+        match req.path() {
+            "/ignore" => res.body("ignored"),
+            "/upload" => {
+                req.body().limit(64).read_to_end(&mut data)?;
+                res.body_vec(data);
+            }
+            _ => {
+                req.body().read_to_end(&mut data)?;
+                res.body_vec(data);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start a test server with a 16 byte body limit on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    let config = HttpConfig::new().with_max_body_size(16);
+    start_server(ServerBuilder::new(HttpServer(LimitService)).config(config))
+}
+
+#[test]
+fn test_declared_length_over_limit_gets_413() {
+    let (handle, port) = start_test_server();
+
+    // only the head is sent, the server must not wait for the body
+    let mut stream = connect(port);
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n")
+        .unwrap();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(response.contains("Connection: close"));
+
+    // at the limit
+    let mut stream = connect(port);
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 16\r\n\r\n0123456789abcdef")
+        .unwrap();
+    let response = read_until(&mut stream, "0123456789abcdef");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_chunked_body_over_limit_gets_413() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+              8\r\n01234567\r\n8\r\n89abcdef\r\n1\r\n!\r\n0\r\n\r\n",
+        )
+        .unwrap();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(!response.contains("01234567"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_per_request_limit() {
+    let (handle, port) = start_test_server();
+
+    let body = "x".repeat(64);
+    let mut stream = connect(port);
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n40\r\n{body}\r\n0\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let response = read_until(&mut stream, &body);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let mut stream = connect(port);
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n41\r\n{body}!\r\n0\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    // a declared length above the server limit, within the raised one
+    let mut stream = connect(port);
+    let request = format!("POST /upload HTTP/1.1\r\nContent-Length: 64\r\n\r\n{body}");
+    stream.write_all(request.as_bytes()).unwrap();
+    let response = read_until(&mut stream, &body);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let mut stream = connect(port);
+    let request = format!("POST /upload HTTP/1.1\r\nContent-Length: 65\r\n\r\n{body}!");
+    stream.write_all(request.as_bytes()).unwrap();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_unread_bodies() {
+    let (handle, port) = start_server(ServerBuilder::new(HttpServer(LimitService)));
+
+    // a small body is skipped, the pipelined request is served
+    let mut stream = connect(port);
+    stream
+        .write_all(
+            b"POST /ignore HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+              POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nnext",
+        )
+        .unwrap();
+    let response = read_until(&mut stream, "next");
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+    assert!(!response.contains("Connection: close"));
+
+    // a large body is not received just to be discarded
+    let mut stream = connect(port);
+    stream
+        .write_all(b"POST /ignore HTTP/1.1\r\nContent-Length: 100000000\r\n\r\nhello")
+        .unwrap();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Connection: close"));
+    assert!(response.ends_with("ignored"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
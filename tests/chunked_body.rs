@@ -0,0 +1,241 @@
+//! Tests for `Transfer-Encoding: chunked` request bodies
+//!
+//! These tests verify that `BodyReader`:
+//! 1. Decodes chunked bodies, including chunk extensions and trailers
+//! 2. Waits for chunks that arrive in separate packets
+//! 3. Leaves pipelined requests after a chunked body intact
+//! 4. Discards unread chunks when dropped, the chunk framing counts against
+//!    what it is willing to discard
+//! 5. Never parses the chunks of a body the service ignores as a request
+//! 6. Reports malformed framing, an oversized trailer section and a body cut
+//!    short as an error and closes the connection
+
+mod common;
+
+use common::{connect, read_until, start_server};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle};
+use std::io::{self, BufRead, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Echoes the body, `/peek` only looks at the first buffered bytes and
+/// `/ignore` never asks for it
+#[derive(Clone)]
+struct EchoService;
+
+impl HttpService for EchoService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        if req.path() == "/ignore" {
+            res.body("ignored");
+            return Ok(());
+        }
+        let peek = req.path() == "/peek";
+        let mut body = req.body();
+        let data = if peek {
+            body.fill_buf()?[..1].to_vec()
+        } else {
+            let mut data = Vec::new();
+            body.read_to_end(&mut data)?;
+            data
+        };
+        res.body_vec(data);
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(EchoService)))
+}
+
+const CHUNKED_POST: &str = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+
+#[test]
+fn test_chunked_body_is_decoded() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = format!("{CHUNKED_POST}5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "hello world");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Length: 11\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_chunk_extensions_and_trailers_are_skipped() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = format!(
+        "{CHUNKED_POST}A ; name=value\r\n0123456789\r\n1;a;b=\"c\"\r\n!\r\n\
+         0\r\nX-Checksum: abc\r\nX-Other: def\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "0123456789!");
+    assert!(response.contains("Content-Length: 11\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_chunks_in_separate_packets() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream.set_nodelay(true).unwrap();
+    for part in [
+        CHUNKED_POST,
+        "3\r",
+        "\nabc",
+        "\r\n",
+        "4\r\ndefg\r\n0\r\n",
+        "\r\n",
+    ] {
+        stream.write_all(part.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let response = read_until(&mut stream, "abcdefg");
+    assert!(response.ends_with("\r\n\r\nabcdefg"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_pipelined_request_after_chunked_body() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = format!(
+        "{CHUNKED_POST}4\r\nGET \r\n0\r\n\r\nPOST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nnext"
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "next");
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+    assert!(response.contains("\r\n\r\nGET HTTP/1.1 200 OK"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_unread_chunks_are_discarded() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = format!(
+        "POST /peek HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+         3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n{CHUNKED_POST}2\r\nok\r\n0\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "ok");
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+    assert!(response.contains("\r\n\r\na"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_unread_chunk_framing_is_not_drained_forever() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    // 20 bytes of data in 80 KiB of chunk extensions
+    let chunk = format!("1;ext={}\r\nx\r\n", "a".repeat(4096));
+    let request = format!(
+        "POST /peek HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}0\r\n\r\n\
+         {CHUNKED_POST}2\r\nok\r\n0\r\n\r\n",
+        chunk.repeat(20)
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "connection closed");
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1);
+    assert!(response.contains("Connection: close"));
+    assert!(response.ends_with("\r\n\r\nx"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_ignored_chunked_body_closes_the_connection() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    // the chunk data looks like a request of its own
+    let smuggled = "GET /smuggled HTTP/1.1\r\n\r\n";
+    let request = format!(
+        "POST /ignore HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+         {:x}\r\n{smuggled}\r\n0\r\n\r\n",
+        smuggled.len()
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "connection closed");
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1);
+    assert!(response.contains("Connection: close"));
+    assert!(response.ends_with("ignored"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_malformed_chunk_size_is_an_error() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = format!("{CHUNKED_POST}zz\r\nhello\r\n0\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+
+    // the framing is lost, so the connection is closed after the response
+    let response = read_until(&mut stream, "connection closed");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    // the error text stays in the log
+    assert!(response.contains("\r\nContent-Length: 0\r\n"));
+    assert!(!response.contains("invalid chunk size"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_long_trailer_section_is_an_error() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    // every trailer line is short, all of them together are not
+    let trailer = format!("X-Pad: {}\r\n", "a".repeat(1024));
+    let request = format!("{CHUNKED_POST}2\r\nok\r\n0\r\n{}\r\n", trailer.repeat(9));
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_until(&mut stream, "connection closed");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("Connection: close"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_body_cut_short_is_an_error() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello")
+        .unwrap();
+    // the service is waiting for the rest of the body
+    thread::sleep(Duration::from_millis(50));
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+    // the partial body is not taken for the whole one
+    let response = read_until(&mut stream, "connection closed");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(!response.contains("hello"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
@@ -0,0 +1,166 @@
+//! Tests for streamed `Transfer-Encoding: chunked` responses
+//!
+//! These tests verify that `Response::chunked`:
+//! 1. Sends the head and the body as chunks, ending with the last chunk
+//! 2. Keeps the order of pipelined responses around a streamed one
+//! 3. Closes the connection without the last chunk when the service fails
+//! 4. Is rejected for a response that is not attached to a connection
+
+mod common;
+
+use bytes::BytesMut;
+use common::{connect, start_server};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// `/stream` writes numbered lines, `/fail` fails half way, `/last` marks
+/// the last of pipelined requests
+#[derive(Clone)]
+struct StreamService;
+
+impl HttpService for StreamService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/stream" | "/fail" => {
+                let fail = req.path() == "/fail";
+                rsp.header("Content-Type: text/plain");
+                let mut body = rsp.chunked()?;
+                for i in 0..10_000 {
+                    if fail && i == 5_000 {
+                        return Err(io::Error::other("export failed"));
+                    }
+                    writeln!(body, "line {i}")?;
+                }
+                Ok(())
+            }
+            "/last" => {
+                rsp.body("last");
+                Ok(())
+            }
+            _ => {
+                rsp.body("plain");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(StreamService)))
+}
+
+/// Send raw request bytes and read until `end` arrived or the connection is closed
+fn send_raw(port: u16, request: &[u8], end: &[u8]) -> Vec<u8> {
+    let mut stream = connect(port);
+    stream.write_all(request).unwrap();
+
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 4096];
+    while !response.ends_with(end) {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => response.extend_from_slice(&buffer[..n]),
+        }
+    }
+    response
+}
+
+/// Decode a chunked body, returns the data and whether the last chunk was seen
+fn decode_chunked(mut body: &[u8]) -> (Vec<u8>, bool) {
+    let mut data = Vec::new();
+    loop {
+        let Some(pos) = body.windows(2).position(|w| w == b"\r\n") else {
+            return (data, false);
+        };
+        let size = usize::from_str_radix(std::str::from_utf8(&body[..pos]).unwrap(), 16).unwrap();
+        body = &body[pos + 2..];
+        if size == 0 {
+            return (data, body == b"\r\n");
+        }
+        if body.len() < size + 2 {
+            return (data, false);
+        }
+        data.extend_from_slice(&body[..size]);
+        assert_eq!(&body[size..size + 2], b"\r\n");
+        body = &body[size + 2..];
+    }
+}
+
+fn expected_lines(count: usize) -> Vec<u8> {
+    (0..count)
+        .map(|i| format!("line {i}\n"))
+        .collect::<String>()
+        .into_bytes()
+}
+
+fn split_head(response: &[u8]) -> (String, &[u8]) {
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("complete head");
+    let head = String::from_utf8_lossy(&response[..end]).into_owned();
+    (head, &response[end + 4..])
+}
+
+#[test]
+fn test_streamed_body_is_chunked() {
+    let (handle, port) = start_test_server();
+
+    let response = send_raw(port, b"GET /stream HTTP/1.1\r\n\r\n", b"\r\n0\r\n\r\n");
+    let (head, body) = split_head(&response);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("\r\nTransfer-Encoding: chunked"));
+    assert!(head.contains("\r\nContent-Type: text/plain"));
+    assert!(!head.contains("Content-Length"));
+
+    let (data, complete) = decode_chunked(body);
+    assert!(complete, "the body must end with the last chunk");
+    assert_eq!(data, expected_lines(10_000));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_pipelined_responses_keep_their_order() {
+    let (handle, port) = start_test_server();
+
+    let request = b"GET /a HTTP/1.1\r\n\r\nGET /stream HTTP/1.1\r\n\r\nGET /last HTTP/1.1\r\n\r\n";
+    let response = send_raw(port, request, b"\r\n\r\nlast");
+    let text = String::from_utf8_lossy(&response);
+
+    let first = text.find("\r\n\r\nplain").expect("first response");
+    let streamed = text
+        .find("Transfer-Encoding: chunked")
+        .expect("streamed response");
+    let last_chunk = text.find("\r\n0\r\n\r\n").expect("last chunk");
+    let third = text.find("\r\n\r\nlast").expect("third response");
+    assert!(first < streamed && streamed < last_chunk && last_chunk < third);
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_failed_stream_closes_the_connection() {
+    let (handle, port) = start_test_server();
+
+    // reads until the server closes the connection
+    let response = send_raw(port, b"GET /fail HTTP/1.1\r\n\r\n", b"\0");
+    let (head, body) = split_head(&response);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let (data, complete) = decode_chunked(body);
+    assert!(!complete, "a failed body must not end with the last chunk");
+    assert!(expected_lines(5_000).starts_with(&data));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_detached_response_cannot_stream() {
+    let mut buf = BytesMut::new();
+    let mut rsp = Response::_test_new(&mut buf);
+    let err = rsp.chunked().err().expect("no connection to stream to");
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
@@ -0,0 +1,73 @@
+//! Helpers shared by the integration tests
+//!
+//! Every test binary includes this module with `mod common;` and uses the
+//! parts it needs.
+#![allow(dead_code)]
+
+use may_minihttp::{HttpServiceFactory, ServerBuilder, ServerHandle};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Once;
+use std::time::Duration;
+
+static INIT: Once = Once::new();
+
+/// Initialize MAY runtime once for all tests
+pub fn init_may_runtime() {
+    INIT.call_once(|| {
+        may::config().set_stack_size(0x8000);
+    });
+}
+
+/// Start a test server on an OS assigned port
+pub fn start_server<F: HttpServiceFactory>(builder: ServerBuilder<F>) -> (ServerHandle, u16) {
+    init_may_runtime();
+
+    let handle = builder.bind("127.0.0.1:0").expect("Failed to start server");
+    let port = handle.local_addr().port();
+    (handle, port)
+}
+
+/// Connect to a test server, reads give up after 3 seconds
+pub fn connect(port: u16) -> TcpStream {
+    let stream = TcpStream::connect(format!("127.0.0.1:{port}")).expect("Failed to connect");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    stream
+}
+
+/// Read until the received bytes end with `last` or the connection is closed
+pub fn read_until(stream: &mut impl Read, last: &str) -> String {
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !response.ends_with(last.as_bytes()) {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => response.extend_from_slice(&buffer[..n]),
+        }
+    }
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// Read until the server closes the connection
+pub fn read_to_close(stream: &mut impl Read) -> String {
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// Send `request` in one write and read until the server closes the connection
+pub fn send_raw(port: u16, request: impl AsRef<[u8]>) -> String {
+    let mut stream = connect(port);
+    stream.write_all(request.as_ref()).unwrap();
+    read_to_close(&mut stream)
+}
+
+/// `GET` `target` on a connection that is closed after the response
+pub fn get(port: u16, target: &str) -> String {
+    send_raw(
+        port,
+        format!("GET {target} HTTP/1.1\r\nConnection: close\r\n\r\n"),
+    )
+}
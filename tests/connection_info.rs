@@ -0,0 +1,97 @@
+//! Tests for per connection information
+//!
+//! These tests verify that:
+//! 1. `new_service` gets the peer and local address of the connection
+//! 2. Requests see the same connection and the requests served before them
+//! 3. Connection ids count up in accept order
+
+mod common;
+
+use common::{connect, read_to_close, start_server};
+use may_minihttp::{
+    ConnectionInfo, HttpService, HttpServiceFactory, Request, Response, ServerBuilder, ServerHandle,
+};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Answers with what it knows about the connection
+struct InfoService {
+    peer: Option<SocketAddr>,
+}
+
+impl HttpService for InfoService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let info = req.connection_info().expect("served request");
+        assert_eq!(info.peer_addr(), self.peer);
+        let body = format!(
+            "id={} peer={} local={} served={}",
+            info.id(),
+            info.peer_addr().unwrap(),
+            info.local_addr().unwrap(),
+            info.requests_served()
+        );
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+struct InfoFactory;
+
+impl HttpServiceFactory for InfoFactory {
+    type Service = InfoService;
+
+    fn new_service(&self, conn: &ConnectionInfo) -> InfoService {
+        InfoService {
+            peer: conn.peer_addr(),
+        }
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(InfoFactory))
+}
+
+/// Send two pipelined requests, returns the client address and the bodies
+fn exchange(port: u16) -> (SocketAddr, Vec<String>) {
+    let mut stream = connect(port);
+    let request = "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n";
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let response = read_to_close(&mut stream);
+    let bodies = response
+        .split("HTTP/1.1 200 OK")
+        .skip(1)
+        .map(|rsp| rsp.rsplit("\r\n\r\n").next().unwrap().to_owned())
+        .collect();
+    (stream.local_addr().unwrap(), bodies)
+}
+
+fn field<'a>(body: &'a str, name: &str) -> &'a str {
+    body.split(' ')
+        .find_map(|f| f.strip_prefix(name)?.strip_prefix('='))
+        .expect("field")
+}
+
+#[test]
+fn test_connection_info() {
+    let (handle, port) = start_test_server();
+
+    let (client, bodies) = exchange(port);
+    assert_eq!(bodies.len(), 2);
+    for (served, body) in bodies.iter().enumerate() {
+        assert_eq!(field(body, "peer"), client.to_string());
+        assert_eq!(field(body, "local"), format!("127.0.0.1:{port}"));
+        assert_eq!(field(body, "served"), served.to_string());
+    }
+    assert_eq!(field(&bodies[0], "id"), field(&bodies[1], "id"));
+
+    // a later connection gets a higher id
+    let first: u64 = field(&bodies[0], "id").parse().unwrap();
+    let (_, bodies) = exchange(port);
+    let second: u64 = field(&bodies[0], "id").parse().unwrap();
+    assert!(second > first);
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
@@ -0,0 +1,102 @@
+//! Tests for `Expect: 100-continue`
+//!
+//! These tests verify that:
+//! 1. `100 Continue` is sent when the service first reads the body
+//! 2. A service that answers without reading the body never asks for it
+//! 3. Responses to earlier pipelined requests go out before `100 Continue`
+//! 4. No interim response is sent when the body already arrived
+
+mod common;
+
+use common::{connect, read_until, start_server};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// Echoes the body, `/reject` answers `401` without reading it
+#[derive(Clone)]
+struct UploadService;
+
+impl HttpService for UploadService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.path() == "/reject" {
+            rsp.status_code(401, "Unauthorized");
+            return Ok(());
+        }
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        rsp.body_vec(body);
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(UploadService)))
+}
+
+const UPLOAD: &str = "POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n";
+
+#[test]
+fn test_continue_is_sent_on_first_read() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream.write_all(UPLOAD.as_bytes()).unwrap();
+    let interim = read_until(&mut stream, "\r\n\r\n");
+    assert_eq!(interim, "HTTP/1.1 100 Continue\r\n\r\n");
+
+    stream.write_all(b"hello").unwrap();
+    let response = read_until(&mut stream, "hello");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_rejected_upload_is_never_requested() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = UPLOAD.replacen("POST /", "POST /reject", 1);
+    stream.write_all(request.as_bytes()).unwrap();
+    // the body is never sent, the connection ends after the response
+    let response = read_until(&mut stream, "\0");
+    assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+    assert!(!response.contains("100 Continue"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_pipelined_response_goes_first() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    let request = format!("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nfirst{UPLOAD}");
+    stream.write_all(request.as_bytes()).unwrap();
+    let received = read_until(&mut stream, "100 Continue\r\n\r\n");
+    assert!(received.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(received.contains("\r\n\r\nfirstHTTP/1.1 100 Continue\r\n\r\n"));
+
+    stream.write_all(b"again").unwrap();
+    let response = read_until(&mut stream, "again");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_no_continue_for_a_sent_body() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(format!("{UPLOAD}hello").as_bytes())
+        .unwrap();
+    let response = read_until(&mut stream, "hello");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
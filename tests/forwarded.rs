@@ -0,0 +1,198 @@
+//! Tests for client resolution behind trusted proxies
+//!
+//! These tests verify that:
+//! 1. Forwarding headers of untrusted peers are ignored
+//! 2. `Forwarded` and `X-Forwarded-For` chains are walked back through the
+//!    trusted proxies only
+//! 3. The scheme and host come from the proxy next to the client
+//! 4. `X-Real-IP` is used without a chain
+//! 5. Only the configured header family is followed
+
+mod common;
+
+use common::{send_raw, start_server};
+use may_minihttp::{
+    ForwardedHeaders, HttpServer, HttpService, IpNet, Request, Response, ServerBuilder,
+    ServerHandle,
+};
+use std::io;
+use std::time::Duration;
+
+/// Answers with the client, scheme and host of the request
+#[derive(Clone)]
+struct ClientService;
+
+impl HttpService for ClientService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let host = match req.host() {
+            Ok(Some(host)) => host.host().to_owned(),
+            Ok(None) => "-".to_owned(),
+            Err(e) => e.to_string(),
+        };
+        let body = format!(
+            "client={} scheme={} host={host}",
+            req.client_addr().unwrap(),
+            req.scheme()
+        );
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+/// Start a test server trusting the `headers` of `proxies`
+fn start_test_server(proxies: &[&str], headers: ForwardedHeaders) -> (ServerHandle, u16) {
+    let proxies = proxies.iter().map(|net| net.parse::<IpNet>().unwrap());
+    let builder = ServerBuilder::new(HttpServer(ClientService))
+        .trusted_proxies(proxies)
+        .forwarded_headers(headers);
+    start_server(builder)
+}
+
+/// Send a request with `headers` and return the response body
+fn client(port: u16, headers: &str) -> String {
+    let request =
+        format!("GET / HTTP/1.1\r\nHost: origin.test\r\n{headers}Connection: close\r\n\r\n");
+    let response = send_raw(port, request);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    response.rsplit("\r\n\r\n").next().unwrap().to_owned()
+}
+
+#[test]
+fn test_untrusted_peer_headers_are_ignored() {
+    let (handle, port) = start_test_server(&["10.0.0.0/8"], ForwardedHeaders::Forwarded);
+
+    let headers = "Forwarded: for=203.0.113.7;proto=https;host=example.com\r\n\
+                   X-Forwarded-For: 203.0.113.7\r\nX-Real-IP: 203.0.113.7\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=127.0.0.1 scheme=http host=origin.test"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_forwarded_chain() {
+    let (handle, port) =
+        start_test_server(&["127.0.0.1", "10.0.0.0/8"], ForwardedHeaders::Forwarded);
+
+    // no forwarding headers
+    assert_eq!(
+        client(port, ""),
+        "client=127.0.0.1 scheme=http host=origin.test"
+    );
+
+    // the client made up the first entry, 10.1.2.3 is a trusted proxy
+    let headers = "Forwarded: for=192.0.2.1\r\n\
+                   Forwarded: for=198.51.100.9;proto=https;host=example.com, \
+                   for=10.1.2.3;proto=http;host=inner.test\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=198.51.100.9 scheme=https host=example.com"
+    );
+
+    // IPv6 nodes are quoted and may carry a port, names are case-insensitive
+    let headers = "Forwarded: For=\"[2001:db8:cafe::17]:4711\";Proto=HTTPS\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=2001:db8:cafe::17 scheme=https host=origin.test"
+    );
+
+    // a hidden client ends the walk at the proxy that hid it
+    let headers = "Forwarded: for=unknown;proto=https, for=10.1.2.3\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=10.1.2.3 scheme=https host=origin.test"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_x_forwarded_chain() {
+    let (handle, port) =
+        start_test_server(&["127.0.0.1", "10.0.0.0/8"], ForwardedHeaders::XForwarded);
+
+    let headers = "X-Forwarded-For: 192.0.2.1, 198.51.100.9\r\n\
+                   X-Forwarded-For: 10.1.2.3\r\n\
+                   X-Forwarded-Proto: https\r\nX-Forwarded-Host: example.com\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=198.51.100.9 scheme=https host=example.com"
+    );
+
+    // every proxy appended its view, the one next to the client counts
+    let headers = "X-Forwarded-For: 198.51.100.9, 10.1.2.3\r\n\
+                   X-Forwarded-Proto: https, http\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=198.51.100.9 scheme=https host=origin.test"
+    );
+
+    // all hops trusted, the first one is the client
+    let headers = "X-Forwarded-For: 10.9.9.9:5555, 10.1.2.3\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=10.9.9.9 scheme=http host=origin.test"
+    );
+
+    let headers = "X-Real-IP: 198.51.100.9\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=198.51.100.9 scheme=http host=origin.test"
+    );
+
+    let headers = "X-Forwarded-Host: bad host\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=127.0.0.1 scheme=http host=invalid X-Forwarded-Host header"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_other_family_is_ignored() {
+    // the proxy appends `X-Forwarded-For`, the client made up `Forwarded`
+    let (handle, port) = start_test_server(&["127.0.0.1"], ForwardedHeaders::XForwarded);
+    let headers = "Forwarded: for=192.0.2.1;proto=https;host=evil.test\r\n\
+                   X-Forwarded-For: 198.51.100.9\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=198.51.100.9 scheme=http host=origin.test"
+    );
+    assert!(handle.shutdown(Duration::from_secs(1)));
+
+    // the proxy appends `Forwarded`, the client made up the rest
+    let (handle, port) = start_test_server(&["127.0.0.1"], ForwardedHeaders::Forwarded);
+    let headers = "X-Forwarded-For: 192.0.2.1\r\nX-Real-IP: 192.0.2.1\r\n\
+                   X-Forwarded-Proto: https\r\nForwarded: for=198.51.100.9\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=198.51.100.9 scheme=http host=origin.test"
+    );
+    let headers = "X-Forwarded-For: 192.0.2.1\r\nX-Real-IP: 192.0.2.1\r\n";
+    assert_eq!(
+        client(port, headers),
+        "client=127.0.0.1 scheme=http host=origin.test"
+    );
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_ip_net() {
+    let net: IpNet = "192.168.1.77/24".parse().unwrap();
+    assert_eq!(net.to_string(), "192.168.1.0/24");
+    assert!(net.contains("192.168.1.200".parse().unwrap()));
+    assert!(net.contains("::ffff:192.168.1.1".parse().unwrap()));
+    assert!(!net.contains("192.168.2.1".parse().unwrap()));
+    assert!(!net.contains("::1".parse().unwrap()));
+
+    let any: IpNet = "::/0".parse().unwrap();
+    assert!(any.contains("2001:db8::1".parse().unwrap()));
+    assert!(!any.contains("10.0.0.1".parse().unwrap()));
+
+    for invalid in ["10.0.0.0/", "10.0.0.0/+8", "::/129", "example.com/8"] {
+        assert!(invalid.parse::<IpNet>().is_err(), "{invalid}");
+    }
+}
@@ -0,0 +1,136 @@
+//! Tests for `ServerHandle::shutdown`
+//!
+//! These tests verify that shutting down a server:
+//! 1. Stops accepting new connections
+//! 2. Closes idle keep-alive connections right away
+//! 3. Finishes in-flight and pipelined requests, marking the last response
+//!    with `Connection: close`
+//! 4. Force closes connections that are still open after the deadline
+
+mod common;
+
+use common::{connect, read_to_close, start_server};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Service that answers `/slow` after a short delay
+#[derive(Clone)]
+struct SlowService;
+
+impl HttpService for SlowService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        if req.path() == "/slow" {
+            may::coroutine::sleep(Duration::from_millis(300));
+        }
+        res.body("done");
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(SlowService)))
+}
+
+#[test]
+fn test_shutdown_closes_idle_connections() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("done"));
+
+    // the connection is now idle in keep-alive
+    let start = Instant::now();
+    assert!(
+        handle.shutdown(Duration::from_secs(5)),
+        "Should drain in time"
+    );
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    assert!(read_to_close(&mut stream).is_empty());
+}
+
+#[test]
+fn test_shutdown_stops_accepting() {
+    let (handle, port) = start_test_server();
+    assert!(handle.shutdown(Duration::from_secs(1)));
+
+    let refused = match TcpStream::connect(("127.0.0.1", port)) {
+        Err(_) => true,
+        Ok(mut stream) => {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").ok();
+            read_to_close(&mut stream).is_empty()
+        }
+    };
+    assert!(refused, "No request should be served after shutdown");
+}
+
+#[test]
+fn test_shutdown_finishes_in_flight_request() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream.write_all(b"GET /slow HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let shutdown = thread::spawn(move || handle.shutdown(Duration::from_secs(5)));
+
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200"), "got: {response}");
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(response.ends_with("done"));
+    assert!(shutdown.join().unwrap(), "Should drain in time");
+}
+
+#[test]
+fn test_shutdown_finishes_pipelined_requests() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"GET /slow HTTP/1.1\r\n\r\nGET /slow HTTP/1.1\r\n\r\n")
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let shutdown = thread::spawn(move || handle.shutdown(Duration::from_secs(5)));
+
+    let response = read_to_close(&mut stream);
+    assert_eq!(
+        response.matches("HTTP/1.1 200").count(),
+        2,
+        "got: {response}"
+    );
+    // only the last response announces the close
+    assert_eq!(response.matches("Connection: close").count(), 1);
+    let last = response.rfind("HTTP/1.1 200").unwrap();
+    assert!(response[last..].contains("Connection: close"));
+    assert!(shutdown.join().unwrap(), "Should drain in time");
+}
+
+#[test]
+fn test_shutdown_force_closes_after_deadline() {
+    let (handle, port) = start_test_server();
+
+    // an incomplete request head keeps the connection busy
+    let mut stream = connect(port);
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: loc").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    assert!(
+        !handle.shutdown(Duration::from_millis(300)),
+        "The straggler should be force closed"
+    );
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    assert!(read_to_close(&mut stream).is_empty());
+}
@@ -0,0 +1,110 @@
+//! Tests for response header validation
+//!
+//! These tests verify that:
+//! 1. A header value with CR or LF never reaches the wire, the client gets
+//!    `500 Internal Server Error` instead
+//! 2. Invalid header names, lines without `:` and reason phrases with a line
+//!    break are rejected the same way
+//! 3. A streamed response fails before its head is sent
+//! 4. Valid headers are written as they are
+
+mod common;
+
+use common::{get, start_server};
+use may_minihttp::{HttpConfig, HttpServer, HttpService, Request, Response, ServerBuilder};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Reflects the query into a `Location` header, other paths set a broken
+/// head
+#[derive(Clone)]
+struct RedirectService;
+
+impl HttpService for RedirectService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path().split_once('?') {
+            Some(("/redirect", to)) => {
+                let to = to.replace("%0D", "\r").replace("%0A", "\n");
+                rsp.status_code(302, "Found");
+                rsp.headers_mut().insert("Location", to);
+            }
+            _ => match req.path() {
+                "/name" => {
+                    rsp.headers_mut().insert("Bad Name", "value");
+                }
+                "/line" => {
+                    rsp.header("no colon here");
+                }
+                "/nul" => {
+                    rsp.header("X-Nul: a\0b");
+                }
+                "/reason" => {
+                    rsp.status_code(200, "OK\r\nX-Injected: yes");
+                }
+                "/stream" => {
+                    rsp.header("X-Stream: a\r\nX-Injected: yes");
+                    rsp.chunked()?.write_all(b"streamed")?;
+                }
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Start a test server with strict header checks on an OS assigned port
+fn start_test_server() -> (may_minihttp::ServerHandle, u16) {
+    let config = HttpConfig::new().with_strict_headers(true);
+    start_server(ServerBuilder::new(HttpServer(RedirectService)).config(config))
+}
+
+#[test]
+fn test_reflected_line_break_is_rejected() {
+    let (handle, port) = start_test_server();
+
+    let response = get(port, "/redirect?/home");
+    assert!(response.starts_with("HTTP/1.1 302 Found\r\n"));
+    assert!(response.contains("\r\nLocation: /home\r\n"));
+
+    for injected in ["/%0D%0AX-Injected:%20yes", "/%0AX-Injected:%20yes"] {
+        let response = get(port, &format!("/redirect?{injected}"));
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(!response.contains("X-Injected"));
+    }
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_malformed_heads_are_rejected() {
+    let (handle, port) = start_test_server();
+
+    for path in ["/name", "/line", "/nul", "/reason"] {
+        let response = get(port, path);
+        assert!(
+            response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{path}: {response}"
+        );
+        assert!(!response.contains("X-Injected"));
+    }
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_streamed_head_is_rejected() {
+    let (handle, port) = start_test_server();
+
+    let response = get(port, "/stream");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(!response.contains("X-Injected"));
+    assert!(!response.contains("streamed"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_strict_headers_config() {
+    assert!(!HttpConfig::default().strict_headers);
+    assert!(HttpConfig::new().with_strict_headers(true).strict_headers);
+}
@@ -0,0 +1,108 @@
+//! Tests for catching panics of the service
+//!
+//! These tests verify that:
+//! 1. A panic is answered with `500 Internal Server Error` and the
+//!    connection keeps serving requests
+//! 2. Headers and body set before the panic are not sent
+//! 3. `ErrorHandler::panic` customizes the response
+//! 4. A panic while streaming closes the connection
+
+mod common;
+
+use common::{send_raw, start_server};
+use may_minihttp::{
+    ErrorHandler, HttpConfig, HttpServer, HttpService, Request, Response, ServerBuilder,
+    ServerHandle,
+};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Panics on `/panic` and `/stream`, answers `ok` otherwise
+#[derive(Clone)]
+struct PanicService;
+
+impl HttpService for PanicService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/panic" => {
+                rsp.header("X-Secret: leaked");
+                rsp.body("partial");
+                panic!("handler bug");
+            }
+            "/stream" => {
+                rsp.chunked()?.write_all(b"partial")?;
+                panic!("handler bug while streaming");
+            }
+            _ => rsp.body("ok"),
+        }
+        Ok(())
+    }
+}
+
+/// Start a test server that catches panics on an OS assigned port
+fn start_test_server(builder: ServerBuilder<HttpServer<PanicService>>) -> (ServerHandle, u16) {
+    start_server(builder.config(HttpConfig::new().with_catch_panics(true)))
+}
+
+#[test]
+fn test_panic_is_answered_and_connection_kept() {
+    let (handle, port) = start_test_server(ServerBuilder::new(HttpServer(PanicService)));
+
+    let response = send_raw(
+        port,
+        "GET /panic HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(!response.contains("X-Secret"));
+    assert!(!response.contains("partial"));
+    // the pipelined request is still served
+    let second = response
+        .find("HTTP/1.1 200 OK\r\n")
+        .expect("second response");
+    assert!(response[..second].contains("\r\nContent-Length: 0\r\n"));
+    assert!(response.ends_with("\r\n\r\nok"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+struct PanicPage;
+
+impl ErrorHandler for PanicPage {
+    fn panic(&self, rsp: &mut Response) {
+        rsp.header("Content-Type: text/plain");
+        rsp.body("something went wrong");
+    }
+}
+
+#[test]
+fn test_error_handler_customizes_panic_response() {
+    let (handle, port) =
+        start_test_server(ServerBuilder::new(HttpServer(PanicService)).error_handler(PanicPage));
+
+    let response = send_raw(port, "GET /panic HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(response.contains("\r\nContent-Type: text/plain\r\n"));
+    assert!(response.ends_with("\r\n\r\nsomething went wrong"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_panic_while_streaming_closes_connection() {
+    let (handle, port) = start_test_server(ServerBuilder::new(HttpServer(PanicService)));
+
+    // the second request is never answered
+    let response = send_raw(port, "GET /stream HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nTransfer-Encoding: chunked\r\n"));
+    assert!(!response.contains("0\r\n\r\n"));
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1);
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_catch_panics_config() {
+    assert!(!HttpConfig::default().catch_panics);
+    assert!(HttpConfig::new().with_catch_panics(true).catch_panics);
+}
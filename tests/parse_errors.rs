@@ -0,0 +1,267 @@
+//! Tests for the responses to malformed requests
+//!
+//! These tests verify that a request the server cannot decode is answered
+//! with the matching status before the connection is closed:
+//! 1. `400 Bad Request` for malformed request heads
+//! 2. `431 Request Header Fields Too Large` for too many headers or a head
+//!    over `max_head_size`
+//! 3. `414 URI Too Long` for request targets over `max_uri_len`
+//! 4. `505 HTTP Version Not Supported` for unknown HTTP versions
+//! 5. `400 Bad Request` for invalid `Content-Length` values and ambiguous
+//!    body framing
+//! 6. An `ErrorHandler` can customize the response
+
+mod common;
+
+use common::{send_raw, start_server};
+use may_minihttp::{
+    ErrorHandler, HttpConfig, HttpServer, HttpService, ParseError, Request, Response,
+    ServerBuilder, ServerHandle,
+};
+use std::io::{self, Read};
+use std::time::Duration;
+
+#[derive(Clone)]
+struct TestService;
+
+impl HttpService for TestService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        if body.is_empty() {
+            res.body("OK");
+        } else {
+            res.body_vec(body);
+        }
+        Ok(())
+    }
+}
+
+fn default_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(TestService)))
+}
+
+#[test]
+fn test_malformed_method_gets_400() {
+    let (handle, port) = default_server();
+
+    let response = send_raw(port, b"G@T / HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("Connection: close"));
+    assert!(response.contains("Content-Length: 0"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_too_many_headers_gets_431() {
+    let (handle, port) = default_server();
+
+    let mut request = String::from("GET / HTTP/1.1\r\n");
+    for i in 0..17 {
+        request.push_str(&format!("X-Custom-{i}: value{i}\r\n"));
+    }
+    request.push_str("\r\n");
+
+    let response = send_raw(port, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    assert!(response.contains("Connection: close"));
+    assert!(!response.contains("OK"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_large_head_gets_431() {
+    let config = HttpConfig::new().with_max_head_size(1024);
+    let (handle, port) = start_server(ServerBuilder::new(HttpServer(TestService)).config(config));
+
+    let head = |value_len| format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "a".repeat(value_len));
+    // the first request is at the limit, the pipelined second one over it
+    let at_limit = head(1024 - 27);
+    assert_eq!(at_limit.len(), 1024);
+    let response = send_raw(port, format!("{at_limit}{}", head(1024 - 26)));
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+    // rejected without waiting for the end of the head
+    let response = send_raw(
+        port,
+        format!("GET / HTTP/1.1\r\nX-Big: {}", "a".repeat(2048)),
+    );
+    assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    assert!(response.contains("Connection: close"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_long_uri_gets_414() {
+    let config = HttpConfig::new().with_max_uri_len(64);
+    let (handle, port) = start_server(ServerBuilder::new(HttpServer(TestService)).config(config));
+
+    // the first request is at the limit, the pipelined second one over it
+    let request = format!(
+        "GET /{} HTTP/1.1\r\n\r\nGET /{} HTTP/1.1\r\n\r\n",
+        "a".repeat(63),
+        "a".repeat(64)
+    );
+    let response = send_raw(port, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("HTTP/1.1 414 URI Too Long\r\n"));
+
+    // rejected before the request line is complete
+    let request = format!("GET /{}", "a".repeat(128));
+    let response = send_raw(port, request.as_bytes());
+    assert!(response.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_unknown_version_gets_505() {
+    let (handle, port) = default_server();
+
+    let response = send_raw(port, b"GET / HTTP/2.0\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+
+    // not a version at all
+    let response = send_raw(port, b"GET / HTXP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_pipelined_requests_before_bad_one_are_served() {
+    let (handle, port) = default_server();
+
+    let response = send_raw(port, b"GET /a HTTP/1.1\r\n\r\nG@T /b HTTP/1.1\r\n\r\n");
+    let ok = response
+        .find("HTTP/1.1 200 OK")
+        .expect("first request served");
+    let bad = response
+        .find("HTTP/1.1 400 Bad Request")
+        .expect("then rejected");
+    assert!(ok < bad);
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_invalid_content_length_gets_400() {
+    let (handle, port) = default_server();
+
+    for value in [
+        &b"abc"[..],
+        b"-1",
+        b"+5",
+        b"0x10",
+        b"",
+        b"99999999999999999999999999",
+        b"5, 6",
+        b"\xff",
+    ] {
+        let mut request = b"POST / HTTP/1.1\r\nContent-Length: ".to_vec();
+        request.extend_from_slice(value);
+        request.extend_from_slice(b"\r\n\r\nhello");
+        let response = send_raw(port, &request);
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "Content-Length {:?} must be rejected",
+            String::from_utf8_lossy(value)
+        );
+    }
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_conflicting_content_lengths_get_400() {
+    let (handle, port) = default_server();
+
+    let response = send_raw(
+        port,
+        b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!",
+    );
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    // repeated identical values are the same length
+    let response = send_raw(
+        port,
+        b"POST / HTTP/1.1\r\nContent-Length: 5, 5\r\nContent-Length: 5\r\n\r\nhello\
+          G@T / HTTP/1.1\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\n\r\nhelloHTTP/1.1 400 Bad Request"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_content_length_with_transfer_encoding_gets_400() {
+    let (handle, port) = default_server();
+
+    // a classic request smuggling attempt
+    let response = send_raw(
+        port,
+        b"POST / HTTP/1.1\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n\
+          0\r\n\r\nGET /admin HTTP/1.1\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert_eq!(response.matches("HTTP/1.1").count(), 1);
+
+    // the final coding must be chunked to find the end of the body
+    let response = send_raw(
+        port,
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+struct JsonErrors;
+
+impl ErrorHandler for JsonErrors {
+    fn parse_error(&self, err: &ParseError, rsp: &mut Response) {
+        rsp.header("Content-Type: application/json");
+        rsp.body_vec(format!(r#"{{"status":{}}}"#, err.status().as_u16()).into_bytes());
+    }
+}
+
+#[test]
+fn test_error_handler_customizes_response() {
+    let (handle, port) =
+        start_server(ServerBuilder::new(HttpServer(TestService)).error_handler(JsonErrors));
+
+    let response = send_raw(port, b"G@T / HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(response.contains("Content-Type: application/json"));
+    assert!(response.contains("Connection: close"));
+    assert!(response.ends_with(r#"{"status":400}"#));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_parse_error_status() {
+    let e = ParseError::TooManyHeaders {
+        received: 20,
+        limit: 16,
+    };
+    assert_eq!(e.status().as_u16(), 431);
+    assert_eq!(
+        e.to_string(),
+        "too many headers: received 20, limit is 16 (over by 4)"
+    );
+    assert_eq!(
+        ParseError::HeadTooLarge { limit: 1024 }.status().as_u16(),
+        431
+    );
+    assert_eq!(ParseError::UriTooLong { limit: 64 }.status().as_u16(), 414);
+    assert_eq!(ParseError::VersionNotSupported.status().as_u16(), 505);
+
+    let io_err = io::Error::from(ParseError::BadRequest(httparse::Error::Token));
+    assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,166 @@
+//! Tests for connection persistence
+//!
+//! These tests verify that:
+//! 1. HTTP/1.1 connections stay open unless the client sends
+//!    `Connection: close`, requests pipelined after it are not served
+//! 2. HTTP/1.0 connections close unless the client asks for `keep-alive`,
+//!    and the response echoes the HTTP/1.0 version
+//! 3. `Response::close_connection` closes the connection after the response
+//! 4. A streamed response to HTTP/1.0 is not chunked and ends with the
+//!    connection
+
+mod common;
+
+use common::{connect, start_server};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// `/bye` closes the connection, `/stream` streams the body
+#[derive(Clone)]
+struct TestService;
+
+impl HttpService for TestService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/bye" => {
+                rsp.close_connection().body("bye");
+            }
+            "/stream" => {
+                let mut body = rsp.chunked()?;
+                body.write_all(b"streamed body")?;
+            }
+            _ => rsp.body("hi"),
+        }
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(TestService)))
+}
+
+/// Read until the response ends with `end`, returns it and whether the
+/// server closed the connection
+fn read_response(stream: &mut TcpStream, end: &str) -> (String, bool) {
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !response.ends_with(end.as_bytes()) {
+        match stream.read(&mut buffer) {
+            Ok(0) => return (String::from_utf8_lossy(&response).into_owned(), true),
+            Err(e) => panic!("no complete response: {e}"),
+            Ok(n) => response.extend_from_slice(&buffer[..n]),
+        }
+    }
+    (String::from_utf8_lossy(&response).into_owned(), false)
+}
+
+/// Whether the server closes the connection within the read timeout
+fn is_closed(stream: &mut TcpStream) -> bool {
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    matches!(stream.read(&mut [0u8; 64]), Ok(0))
+}
+
+#[test]
+fn test_http11_keeps_the_connection() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    for _ in 0..3 {
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let (response, _) = read_response(&mut stream, "hi");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(!response.contains("Connection:"));
+    }
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_connection_close_is_honored() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"GET /a HTTP/1.1\r\nConnection: Close\r\n\r\nGET /b HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let (response, _) = read_response(&mut stream, "hi");
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(
+        is_closed(&mut stream),
+        "the pipelined request must not be served"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_http10_closes_by_default() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let (response, _) = read_response(&mut stream, "hi");
+    assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut stream));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_http10_keep_alive() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    for _ in 0..2 {
+        stream
+            .write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+            .unwrap();
+        let (response, closed) = read_response(&mut stream, "hi");
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.contains("\r\nConnection: keep-alive\r\n"));
+        assert!(!closed);
+    }
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_handler_closes_the_connection() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"GET /bye HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n")
+        .unwrap();
+    let (response, _) = read_response(&mut stream, "bye");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(is_closed(&mut stream));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_http10_stream_is_not_chunked() {
+    let (handle, port) = start_test_server();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+        .unwrap();
+    // the body ends with the connection
+    let (response, closed) = read_response(&mut stream, "\0");
+    assert!(closed);
+    assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(!response.contains("Transfer-Encoding"));
+    assert!(response.contains("\r\nConnection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\nstreamed body"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
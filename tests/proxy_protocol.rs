@@ -0,0 +1,217 @@
+//! Tests for PROXY protocol listeners
+//!
+//! These tests verify that:
+//! 1. v1 and v2 headers replace the addresses of the connection
+//! 2. v2 TLVs are available from the connection info
+//! 3. `LOCAL` and `UNKNOWN` headers keep the socket addresses
+//! 4. Connections without a valid header in time are closed unanswered
+
+mod common;
+
+use common::{connect, read_to_close, send_raw, start_server};
+use may_minihttp::{
+    ConnectionInfo, HttpConfig, HttpService, HttpServiceFactory, ProxyHeader, Request, Response,
+    ServerBuilder, ServerHandle,
+};
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Answers with the addresses and the authority TLV of the connection
+struct ProxyService {
+    // the peer address seen when the service was created
+    peer: String,
+}
+
+impl HttpService for ProxyService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let info = req.connection_info().expect("served request");
+        let header = info.proxy_header().expect("PROXY header");
+        let authority = header
+            .tlv(ProxyHeader::TLV_AUTHORITY)
+            .map(|v| String::from_utf8_lossy(v).into_owned());
+        let body = format!(
+            "new={} peer={} local={} version={} authority={}",
+            self.peer,
+            info.peer_addr().unwrap(),
+            info.local_addr().unwrap(),
+            header.version(),
+            authority.as_deref().unwrap_or("-"),
+        );
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+struct ProxyFactory;
+
+impl HttpServiceFactory for ProxyFactory {
+    type Service = ProxyService;
+
+    fn new_service(&self, conn: &ConnectionInfo) -> ProxyService {
+        ProxyService {
+            peer: conn.peer_addr().unwrap().to_string(),
+        }
+    }
+}
+
+/// Start a PROXY protocol server on an OS assigned port
+fn start_test_server(timeout: Duration) -> (ServerHandle, u16) {
+    let config = HttpConfig::new()
+        .with_proxy_protocol(true)
+        .with_proxy_header_timeout(timeout);
+    start_server(ServerBuilder::new(ProxyFactory).config(config))
+}
+
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n";
+
+/// A v2 `PROXY` header for a stream connection of `family` followed by the
+/// request
+fn v2(command: u8, family: u8, addrs: &[u8], tlvs: &[u8]) -> Vec<u8> {
+    let mut buf = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    buf.push(0x20 | command);
+    buf.push(family << 4 | 1);
+    buf.extend_from_slice(&((addrs.len() + tlvs.len()) as u16).to_be_bytes());
+    buf.extend_from_slice(addrs);
+    buf.extend_from_slice(tlvs);
+    buf.extend_from_slice(REQUEST);
+    buf
+}
+
+fn body(response: &str) -> &str {
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    response.rsplit("\r\n\r\n").next().unwrap()
+}
+
+#[test]
+fn test_v1_header() {
+    let (handle, port) = start_test_server(Duration::from_secs(3));
+
+    let mut request = b"PROXY TCP4 203.0.113.7 198.51.100.1 4242 443\r\n".to_vec();
+    request.extend_from_slice(REQUEST);
+    assert_eq!(
+        body(&send_raw(port, &request)),
+        "new=203.0.113.7:4242 peer=203.0.113.7:4242 local=198.51.100.1:443 version=1 authority=-"
+    );
+
+    let mut request = b"PROXY TCP6 2001:db8::7 2001:db8::1 4242 443\r\n".to_vec();
+    request.extend_from_slice(REQUEST);
+    assert_eq!(
+        body(&send_raw(port, &request)),
+        "new=[2001:db8::7]:4242 peer=[2001:db8::7]:4242 local=[2001:db8::1]:443 version=1 authority=-"
+    );
+
+    // a header that arrives in pieces
+    let mut stream = connect(port);
+    stream.write_all(b"PROXY TCP4 203.0.113.7 ").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"198.51.100.1 4242 443\r\nGET").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(&REQUEST[3..]).unwrap();
+    assert_eq!(
+        body(&read_to_close(&mut stream)),
+        "new=203.0.113.7:4242 peer=203.0.113.7:4242 local=198.51.100.1:443 version=1 authority=-"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_v2_header() {
+    let (handle, port) = start_test_server(Duration::from_secs(3));
+
+    // TCP over IPv4 with a NOOP and an authority TLV
+    let addrs = [203, 0, 113, 7, 198, 51, 100, 1, 0x10, 0x92, 0x01, 0xbb];
+    let tlvs = b"\x04\x00\x02\0\0\x02\x00\x0bexample.com";
+    assert_eq!(
+        body(&send_raw(port, v2(1, 1, &addrs, tlvs))),
+        "new=203.0.113.7:4242 peer=203.0.113.7:4242 local=198.51.100.1:443 version=2 \
+         authority=example.com"
+    );
+
+    // TCP over IPv6
+    let mut addrs = vec![0x20, 0x01, 0x0d, 0xb8];
+    addrs.extend_from_slice(&[0; 11]);
+    addrs.extend_from_slice(&[7, 0x20, 0x01, 0x0d, 0xb8]);
+    addrs.extend_from_slice(&[0; 11]);
+    addrs.extend_from_slice(&[1, 0x10, 0x92, 0x01, 0xbb]);
+    assert_eq!(
+        body(&send_raw(port, v2(1, 2, &addrs, b""))),
+        "new=[2001:db8::7]:4242 peer=[2001:db8::7]:4242 local=[2001:db8::1]:443 version=2 \
+         authority=-"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_headers_without_addresses_keep_socket_addresses() {
+    let (handle, port) = start_test_server(Duration::from_secs(3));
+    let local = format!("local=127.0.0.1:{port}");
+
+    // a `LOCAL` health check ignores the addresses it carries
+    let addrs = [203, 0, 113, 7, 198, 51, 100, 1, 0x10, 0x92, 0x01, 0xbb];
+    let response = send_raw(port, v2(0, 1, &addrs, b""));
+    assert!(body(&response).starts_with("new=127.0.0.1:"), "{response}");
+    assert!(body(&response).contains(&local), "{response}");
+
+    let mut request = b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n".to_vec();
+    request.extend_from_slice(REQUEST);
+    let response = send_raw(port, &request);
+    assert!(body(&response).starts_with("new=127.0.0.1:"), "{response}");
+    assert!(
+        body(&response).ends_with("version=1 authority=-"),
+        "{response}"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_invalid_headers_are_rejected() {
+    let (handle, port) = start_test_server(Duration::from_secs(3));
+
+    let invalid: &[&[u8]] = &[
+        // no header at all
+        b"GET / HTTP/1.1\r\n\r\n",
+        // address of the wrong family, the parser tests cover the rest
+        b"PROXY TCP4 203.0.113.7 ::1 4242 443\r\n",
+    ];
+    for header in invalid {
+        let mut request = header.to_vec();
+        request.extend_from_slice(REQUEST);
+        assert_eq!(
+            send_raw(port, &request),
+            "",
+            "{:?}",
+            String::from_utf8_lossy(header)
+        );
+    }
+
+    // longer than the 107 bytes a v1 header may have
+    let mut request = b"PROXY UNKNOWN ".to_vec();
+    request.resize(120, b'x');
+    request.extend_from_slice(b"\r\n");
+    request.extend_from_slice(REQUEST);
+    assert_eq!(send_raw(port, &request), "");
+
+    // v2 TLV longer than the header
+    let addrs = [203, 0, 113, 7, 198, 51, 100, 1, 0x10, 0x92, 0x01, 0xbb];
+    assert_eq!(send_raw(port, v2(1, 1, &addrs, b"\x02\x00\x09host")), "");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_header_timeout() {
+    let (handle, port) = start_test_server(Duration::from_millis(200));
+
+    let started = Instant::now();
+    // a partial header is not enough
+    assert_eq!(send_raw(port, b"PROXY TCP4 203.0.113.7"), "");
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
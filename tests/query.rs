@@ -0,0 +1,112 @@
+//! Tests for the request target accessors
+//!
+//! These tests verify that:
+//! 1. `uri_path` strips the query string and percent-decodes the path
+//! 2. `query` returns the raw query string
+//! 3. `query_param` and `query_param_all` decode values and keep repeated
+//!    keys in order
+
+mod common;
+
+use common::start_server;
+use may_minihttp::{
+    HttpServer, HttpService, QueryPairs, Request, Response, ServerBuilder, ServerHandle,
+};
+use std::borrow::Cow;
+use std::io;
+use std::time::Duration;
+
+/// Answers with one line per accessor
+#[derive(Clone)]
+struct TargetService;
+
+impl HttpService for TargetService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let tags = req.query_param_all("tag").collect::<Vec<_>>().join(",");
+        let body = format!(
+            "path={}\nborrowed={}\nquery={}\nq={}\ntags={}\n",
+            req.uri_path(),
+            matches!(req.uri_path(), Cow::Borrowed(_)),
+            req.query().unwrap_or("<none>"),
+            req.query_param("q").as_deref().unwrap_or("<none>"),
+            tags,
+        );
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(TargetService)))
+}
+
+/// Send a request for `target` and return the response body
+fn get(port: u16, target: &str) -> String {
+    let response = common::get(port, target);
+    let (_, body) = response.split_once("\r\n\r\n").expect("complete head");
+    body.to_owned()
+}
+
+#[test]
+fn test_plain_path_is_borrowed() {
+    let (handle, port) = start_test_server();
+
+    let body = get(port, "/users/42");
+    assert_eq!(
+        body,
+        "path=/users/42\nborrowed=true\nquery=<none>\nq=<none>\ntags=\n"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_path_is_decoded_without_query() {
+    let (handle, port) = start_test_server();
+
+    let body = get(port, "/caf%C3%A9/a+b%2Fc?q=x");
+    assert!(body.starts_with("path=/café/a+b/c\nborrowed=false\nquery=q=x\n"));
+
+    // malformed escapes are kept
+    let body = get(port, "/100%/%zz");
+    assert!(body.starts_with("path=/100%/%zz\n"));
+
+    // absolute form
+    let body = get(port, "http://example.com/a%20b?q=1");
+    assert!(body.starts_with("path=/a b\nborrowed=false\nquery=q=1\nq=1\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_query_params() {
+    let (handle, port) = start_test_server();
+
+    let body = get(
+        port,
+        "/search?tag=rust&q=hello+world%21&tag=http%2F1.1&&flag&tag=",
+    );
+    assert_eq!(
+        body,
+        "path=/search\nborrowed=true\nquery=tag=rust&q=hello+world%21&tag=http%2F1.1&&flag&tag=\n\
+         q=hello world!\ntags=rust,http/1.1,\n"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_query_pairs() {
+    let pairs: Vec<_> = QueryPairs::new("a=1&b&=2&c=%ZZ&d=%e2%82%ac&&").collect();
+    let expected = [("a", "1"), ("b", ""), ("", "2"), ("c", "%ZZ"), ("d", "€")];
+    assert_eq!(pairs.len(), expected.len());
+    for ((key, value), (k, v)) in pairs.iter().zip(expected) {
+        assert_eq!((&**key, &**value), (k, v));
+    }
+
+    // invalid utf-8 is replaced
+    let (_, value) = QueryPairs::new("x=%FF").next().unwrap();
+    assert_eq!(value, "\u{FFFD}");
+    assert!(QueryPairs::new("").next().is_none());
+}
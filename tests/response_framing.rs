@@ -0,0 +1,115 @@
+//! Tests for responses without a body
+//!
+//! These tests verify that:
+//! 1. A response to `HEAD` keeps the `Content-Length` of the body it leaves
+//!    out, also for a streamed body
+//! 2. `204 No Content` and `1xx` responses have neither a body nor a
+//!    `Content-Length`
+//! 3. `304 Not Modified` responses have no body
+//! 4. The `Router` serves `HEAD` with the `GET` handler
+
+mod common;
+
+use common::{send_raw, start_server};
+use may_minihttp::{
+    HttpServer, HttpService, Request, Response, Router, ServerBuilder, ServerHandle,
+};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Sets a body on every response, the path picks the status
+#[derive(Clone)]
+struct StatusService;
+
+impl HttpService for StatusService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/204" => rsp.status_code(204, "No Content"),
+            "/304" => rsp.status_code(304, "Not Modified"),
+            "/101" => rsp.status_code(101, "Switching Protocols"),
+            "/stream" => {
+                rsp.chunked()?.write_all(b"streamed")?;
+                return Ok(());
+            }
+            _ => rsp,
+        };
+        rsp.body("hello world");
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server<T: HttpService + Clone + Send + Sync + 'static>(
+    service: T,
+) -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(service)))
+}
+
+/// Send `request` followed by a `GET /last`, returns everything received
+/// before the response to the latter
+///
+/// A response that carried a body by mistake shows up in front of the
+/// second response.
+fn exchange(port: u16, request: &str) -> String {
+    let request = format!("{request}GET /last HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let response = send_raw(port, request);
+    let last = response.rfind("HTTP/1.1 ").expect("second response");
+    assert!(response[last..].ends_with("hello world"));
+    response[..last].to_owned()
+}
+
+#[test]
+fn test_head_keeps_content_length() {
+    let (handle, port) = start_test_server(StatusService);
+
+    let response = exchange(port, "HEAD / HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nContent-Length: 11\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    let response = exchange(port, "HEAD /stream HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_bodiless_statuses() {
+    let (handle, port) = start_test_server(StatusService);
+
+    for status in ["204 No Content", "101 Switching Protocols"] {
+        let code = &status[..3];
+        let response = exchange(port, &format!("GET /{code} HTTP/1.1\r\n\r\n"));
+        assert!(response.starts_with(&format!("HTTP/1.1 {status}\r\n")));
+        assert!(!response.contains("Content-Length"));
+        assert!(response.ends_with("\r\n\r\n"));
+    }
+
+    let response = exchange(port, "GET /304 HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_router_serves_head_with_get() {
+    let router = Router::new()
+        .get("/", |_req, rsp, _params| {
+            rsp.body("hello world");
+            Ok(())
+        })
+        .get("/last", |_req, rsp, _params| {
+            rsp.body("hello world");
+            Ok(())
+        });
+    let (handle, port) = start_test_server(router);
+
+    let response = exchange(port, "HEAD / HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nContent-Length: 11\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
@@ -0,0 +1,144 @@
+//! Tests for the response header collection
+//!
+//! These tests verify that:
+//! 1. Headers can be replaced and removed before the response is sent
+//! 2. `Server` and `Date` set by the service replace the server's own
+//! 3. A `Content-Length` set by the service is only used for `HEAD`
+//! 4. A `Connection` header set by the service is not repeated
+//! 5. More headers than are stored inline are all sent
+
+mod common;
+
+use common::{send_raw, start_server};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle};
+use std::io;
+use std::time::Duration;
+
+/// The path picks the headers
+#[derive(Clone)]
+struct HeaderService;
+
+impl HttpService for HeaderService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.header("Content-Type: text/plain");
+        rsp.header(format!("X-Trace: {}", "abc"));
+        match req.path() {
+            "/replace" => {
+                let headers = rsp.headers_mut();
+                headers.insert("content-type", "application/json");
+                headers.remove("x-trace");
+                headers.append("Vary", "Accept").append("Vary", "Origin");
+            }
+            "/server" => {
+                rsp.header("Server: example");
+                rsp.headers_mut()
+                    .insert("Date", "Thu, 01 Jan 2026 00:00:00 GMT");
+            }
+            "/length" => {
+                rsp.header("Content-Length: 42");
+            }
+            "/many" => {
+                for i in 0..40 {
+                    rsp.header(format!("X-Header-{i}: {i}"));
+                }
+            }
+            "/close" => {
+                rsp.header("Connection: close");
+                rsp.close_connection();
+            }
+            _ => {}
+        }
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(HeaderService)))
+}
+
+/// Send a single request and read the response until the server closes
+fn send_request(port: u16, method: &str, path: &str) -> String {
+    send_raw(
+        port,
+        format!("{method} {path} HTTP/1.1\r\nConnection: close\r\n\r\n"),
+    )
+}
+
+fn count(response: &str, header: &str) -> usize {
+    response.matches(&format!("\r\n{header}:")).count()
+}
+
+#[test]
+fn test_replace_and_remove() {
+    let (handle, port) = start_test_server();
+
+    let response = send_request(port, "GET", "/");
+    assert!(response.contains("\r\nContent-Type: text/plain\r\n"));
+    assert!(response.contains("\r\nX-Trace: abc\r\n"));
+
+    let response = send_request(port, "GET", "/replace");
+    assert!(response.contains("\r\ncontent-type: application/json\r\n"));
+    assert_eq!(count(&response, "Content-Type"), 0);
+    assert_eq!(count(&response, "X-Trace"), 0);
+    assert!(response.contains("\r\nVary: Accept\r\nVary: Origin\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_server_and_date_replace_defaults() {
+    let (handle, port) = start_test_server();
+
+    let response = send_request(port, "GET", "/server");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\nServer: example\r\n"));
+    assert!(response.contains("\r\nDate: Thu, 01 Jan 2026 00:00:00 GMT\r\n"));
+    assert_eq!(count(&response, "Server"), 1);
+    assert_eq!(count(&response, "Date"), 1);
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_content_length_is_not_repeated() {
+    let (handle, port) = start_test_server();
+
+    let response = send_request(port, "GET", "/length");
+    assert!(response.contains("\r\nContent-Length: 5\r\n"));
+    assert_eq!(count(&response, "Content-Length"), 1);
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    // a response to HEAD has no body to measure
+    let response = send_request(port, "HEAD", "/length");
+    assert!(response.contains("\r\nContent-Length: 42\r\n"));
+    assert_eq!(count(&response, "Content-Length"), 1);
+    assert!(response.ends_with("\r\n\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_connection_is_not_repeated() {
+    let (handle, port) = start_test_server();
+
+    let response = send_request(port, "GET", "/close");
+    assert_eq!(count(&response, "Connection"), 1);
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_many_headers() {
+    let (handle, port) = start_test_server();
+
+    let response = send_request(port, "GET", "/many");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    for i in 0..40 {
+        assert!(response.contains(&format!("\r\nX-Header-{i}: {i}\r\n")));
+    }
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
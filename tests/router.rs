@@ -0,0 +1,190 @@
+//! Tests for the `Router` service
+//!
+//! These tests verify that:
+//! 1. Static routes, `:param` captures and `*wildcard` tails are matched
+//! 2. Static segments win over parameters, parameters over wildcards
+//! 3. The query string does not take part in matching
+//! 4. A known path with another method gets `405` and an `Allow` header
+//! 5. Unknown paths get `404` or the `not_found` handler
+//! 6. Mounted routers serve their routes and 404 handler under a prefix
+
+mod common;
+
+use common::{connect, start_server};
+use may_minihttp::{HttpServer, Params, Request, Response, Router, ServerBuilder, ServerHandle};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// Answers with the route name and the captured parameters
+fn echo(name: &'static str) -> impl Fn(Request, &mut Response, &Params) -> io::Result<()> {
+    move |_req, rsp, params| {
+        let mut body = name.to_owned();
+        for (name, value) in params.iter() {
+            body.push_str(&format!(" {name}={value}"));
+        }
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+fn test_router() -> Router {
+    let api = Router::new()
+        .get("/", echo("api root"))
+        .get("/items/:id", echo("api item"))
+        .not_found(|_req, rsp, _params| {
+            rsp.status_code(410, "Gone");
+            rsp.body("no such api");
+            Ok(())
+        });
+
+    Router::new()
+        .get("/", echo("root"))
+        .get("/users", echo("users"))
+        .post("/users", echo("create user"))
+        .get("/users/me", echo("me"))
+        .get("/users/:id", echo("user"))
+        .delete("/users/:id", echo("delete user"))
+        .get("/users/:id/posts/:post", echo("post"))
+        .get("/files/*path", echo("file"))
+        .get("/tags/:tag", echo("tag"))
+        .get("/files/readme", echo("readme"))
+        .get("/unicode/äö", echo("umlaut"))
+        .get("/unicode/äü", echo("other umlaut"))
+        .mount("/api/", api)
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server(router: Router) -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(router)))
+}
+
+/// Send one request, returns the status line, the head and the body
+fn request(port: u16, method: &str, path: &str) -> (String, String, String) {
+    let mut stream = connect(port);
+    let request = format!("{method} {path} HTTP/1.1\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 1024];
+    let (head, body) = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => panic!("incomplete response to {method} {path}"),
+            Ok(n) => response.extend_from_slice(&buffer[..n]),
+        }
+        let text = String::from_utf8_lossy(&response);
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let length = head
+            .lines()
+            .find_map(|l| l.strip_prefix("Content-Length: "))
+            .and_then(|l| l.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        if body.len() >= length {
+            break (head.to_owned(), body.to_owned());
+        }
+    };
+    let status = head.lines().next().unwrap().to_owned();
+    (status, head, body)
+}
+
+#[test]
+fn test_static_and_parameter_routes() {
+    let (handle, port) = start_test_server(test_router());
+
+    for (path, expected) in [
+        ("/", "root"),
+        ("/users", "users"),
+        ("/users/me", "me"),
+        ("/users/42", "user id=42"),
+        ("/users/42/posts/7", "post id=42 post=7"),
+        ("/files/readme", "readme"),
+        ("/files/css/site.css", "file path=css/site.css"),
+        ("/files/", "file path="),
+        ("/unicode/äö", "umlaut"),
+        ("/unicode/äü", "other umlaut"),
+        ("/users/42?fields=name", "user id=42"),
+        ("/tags/a%2Fb%20c", "tag tag=a/b c"),
+    ] {
+        let (status, _, body) = request(port, "GET", path);
+        assert_eq!(status, "HTTP/1.1 200 OK", "GET {path}");
+        assert_eq!(body, expected, "GET {path}");
+    }
+
+    let (_, _, body) = request(port, "POST", "/users");
+    assert_eq!(body, "create user");
+    let (_, _, body) = request(port, "DELETE", "/users/9");
+    assert_eq!(body, "delete user id=9");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_unknown_method_gets_405() {
+    let (handle, port) = start_test_server(test_router());
+
+    let (status, head, _) = request(port, "PUT", "/users/42");
+    assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+    assert!(head.contains("\r\nAllow: GET, DELETE, HEAD\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_unknown_path_gets_404() {
+    let (handle, port) = start_test_server(test_router());
+
+    for path in ["/nope", "/users/42/posts", "/users/", "/unicode/ä"] {
+        let (status, _, body) = request(port, "GET", path);
+        assert_eq!(status, "HTTP/1.1 404 Not Found", "GET {path}");
+        assert!(body.is_empty());
+    }
+
+    // the handler gets the 404 status
+    let router = test_router().not_found(|_req, rsp, _params| {
+        rsp.body("custom");
+        Ok(())
+    });
+    let (custom, custom_port) = start_test_server(router);
+    let (status, _, body) = request(custom_port, "GET", "/nope");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+    assert_eq!(body, "custom");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+    assert!(custom.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_mounted_router() {
+    let (handle, port) = start_test_server(test_router());
+
+    let (_, _, body) = request(port, "GET", "/api");
+    assert_eq!(body, "api root");
+    let (_, _, body) = request(port, "GET", "/api/items/3");
+    assert_eq!(body, "api item id=3");
+
+    // the mounted 404 handler only answers under its prefix
+    let (status, _, body) = request(port, "GET", "/api/nope");
+    assert_eq!(status, "HTTP/1.1 410 Gone");
+    assert_eq!(body, "no such api");
+    let (_, _, body) = request(port, "GET", "/apis");
+    assert!(body.is_empty());
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+#[should_panic(expected = "conflicts")]
+fn test_conflicting_parameter_names() {
+    let _ = Router::new()
+        .get("/users/:id", echo("user"))
+        .get("/users/:name/posts", echo("posts"));
+}
+
+#[test]
+#[should_panic(expected = "duplicate route")]
+fn test_duplicate_route() {
+    let _ = Router::new()
+        .get("/users", echo("users"))
+        .get("/users", echo("users"));
+}
@@ -0,0 +1,157 @@
+//! Tests for runtime server configuration via `ServerBuilder` and `HttpConfig`
+//!
+//! These tests verify that the configured `MaxHeaders` limit reaches the
+//! connection loop, including custom limits that are not one of the
+//! const generic sizes used for the header storage.
+
+mod common;
+
+use bytes::BufMut;
+use common::{init_may_runtime, start_server};
+use may_minihttp::{
+    HttpConfig, HttpServer, HttpService, MaxHeaders, Request, Response, ServerBuilder, ServerHandle,
+};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Simple test service that echoes header count
+#[derive(Clone)]
+struct TestService;
+
+impl HttpService for TestService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        let header_count = req.headers().len();
+        writeln!(res.body_mut().writer(), "Headers: {header_count}")?;
+        Ok(())
+    }
+}
+
+/// Start a test server with the given configuration on an OS assigned port
+fn start_test_server(config: HttpConfig) -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(TestService)).config(config))
+}
+
+/// Send HTTP request with specified number of headers
+fn send_request_with_headers(port: u16, num_headers: usize) -> io::Result<String> {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}"))?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    let mut request = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n");
+    for i in 1..num_headers {
+        request.push_str(&format!("X-Custom-{i}: value{i}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 2048];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                response.extend_from_slice(&buffer[..n]);
+                if response.ends_with(b"\n") {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    String::from_utf8(response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn stop(handle: ServerHandle) {
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn test_builder_large_accepts_40_headers() {
+    let (handle, port) = start_test_server(HttpConfig::new().with_max_headers(MaxHeaders::Large));
+
+    let response = send_request_with_headers(port, 40).expect("Failed to send request");
+    assert!(
+        response.contains("200"),
+        "Should get 200 OK with 40 headers"
+    );
+    assert!(
+        response.contains("Headers: 40"),
+        "Should receive 40 headers"
+    );
+
+    stop(handle);
+}
+
+#[test]
+fn test_builder_custom_limit_is_exact() {
+    let config = HttpConfig::new().with_max_headers(MaxHeaders::Custom(20));
+    let (handle, port) = start_test_server(config);
+
+    let response = send_request_with_headers(port, 20).expect("Failed to send request");
+    assert!(
+        response.contains("Headers: 20"),
+        "Should accept exactly 20 headers"
+    );
+
+    // 21 headers still fit the 32 slot storage, but must be rejected
+    if let Ok(response) = send_request_with_headers(port, 21) {
+        assert!(
+            !response.contains("Headers: 21"),
+            "Handler should not receive 21 headers with a limit of 20"
+        );
+    }
+
+    stop(handle);
+}
+
+#[test]
+fn test_builder_default_config_keeps_16_header_limit() {
+    let (handle, port) = start_test_server(HttpConfig::default());
+
+    let response = send_request_with_headers(port, 16).expect("Failed to send request");
+    assert!(response.contains("Headers: 16"), "Should accept 16 headers");
+
+    if let Ok(response) = send_request_with_headers(port, 17) {
+        assert!(
+            !response.contains("Headers: 17"),
+            "Handler should not receive 17 headers by default"
+        );
+    }
+
+    stop(handle);
+}
+
+#[test]
+fn test_with_headers_server_uses_const_limit() {
+    init_may_runtime();
+    let handle = may_minihttp::HttpServerWithHeaders::<_, 48>(TestService)
+        .start("127.0.0.1:0")
+        .expect("Failed to start server");
+    let port = handle.local_addr().port();
+
+    let response = send_request_with_headers(port, 48).expect("Failed to send request");
+    assert!(response.contains("Headers: 48"), "Should accept 48 headers");
+
+    if let Ok(response) = send_request_with_headers(port, 49) {
+        assert!(
+            !response.contains("Headers: 49"),
+            "Should reject 49 headers"
+        );
+    }
+
+    stop(handle);
+}
+
+#[test]
+fn test_bind_port_zero_reports_assigned_addr() {
+    let (handle, port) = start_test_server(HttpConfig::default());
+    assert_ne!(port, 0, "The OS should assign a real port");
+    assert_eq!(handle.local_addr().ip().to_string(), "127.0.0.1");
+
+    let response = send_request_with_headers(port, 1).expect("Failed to send request");
+    assert!(response.contains("Headers: 1"));
+
+    stop(handle);
+}
@@ -0,0 +1,147 @@
+//! Tests for the responses to failed service calls
+//!
+//! These tests verify that:
+//! 1. The text of an `io::Error` is not sent to the client
+//! 2. An `HttpError` picks the status and its message is the body
+//! 3. An invalid request header is answered with `400 Bad Request`
+//! 4. `ErrorHandler::service_error` builds the response, also after the
+//!    request body was read
+//! 5. Headers set before the error are not sent and the connection stays
+//!    open
+
+mod common;
+
+use common::{get, send_raw, start_server};
+use may_minihttp::{
+    ErrorHandler, HttpError, HttpServer, HttpService, Request, RequestLine, Response,
+    ServerBuilder, StatusCode,
+};
+use std::io;
+use std::time::Duration;
+
+/// The path picks the error
+#[derive(Clone)]
+struct FailingService;
+
+impl HttpService for FailingService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.header("X-Partial: yes");
+        match req.path() {
+            "/io" => Err(io::Error::other("password=hunter2 at /srv/db")),
+            "/missing" => {
+                Err(HttpError::with_message(StatusCode::NOT_FOUND, "no such item").into())
+            }
+            "/forbidden" => Err(HttpError::from(StatusCode::FORBIDDEN))?,
+            "/host" => {
+                req.host()?;
+                Ok(())
+            }
+            "/upload" => {
+                io::copy(&mut req.body(), &mut io::sink())?;
+                Err(io::Error::other("disk full"))
+            }
+            _ => {
+                rsp.body("ok");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn test_error_text_is_not_sent() {
+    let (handle, port) = start_server(ServerBuilder::new(HttpServer(FailingService)));
+
+    let response = send_raw(
+        port,
+        "GET /io HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(!response.contains("hunter2"));
+    // the connection keeps serving requests
+    let second = response
+        .find("HTTP/1.1 200 OK\r\n")
+        .expect("second response");
+    assert!(!response[..second].contains("X-Partial"));
+    assert!(response[..second].ends_with("\r\nContent-Length: 0\r\n\r\n"));
+    assert!(response.ends_with("\r\n\r\nok"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_http_error_picks_status() {
+    let (handle, port) = start_server(ServerBuilder::new(HttpServer(FailingService)));
+
+    let response = get(port, "/missing");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.contains("\r\nContent-Type: text/plain; charset=utf-8\r\n"));
+    assert!(response.ends_with("\r\n\r\nno such item"));
+
+    let response = get(port, "/forbidden");
+    assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    assert!(response.contains("\r\nContent-Length: 0\r\n"));
+
+    let response = send_raw(
+        port,
+        "GET /host HTTP/1.1\r\nHost: a\r\nHost: b\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+/// Answers with RFC 9457 problem details
+struct ProblemJson;
+
+impl ErrorHandler for ProblemJson {
+    fn service_error(&self, err: &io::Error, req: RequestLine<'_>, rsp: &mut Response) {
+        let status = HttpError::from_io(err).map_or(500, |e| e.status().as_u16());
+        rsp.headers_mut()
+            .insert("Content-Type", "application/problem+json");
+        let body = format!(r#"{{"status":{status},"instance":"{}"}}"#, req.path());
+        rsp.body_vec(body.into_bytes());
+    }
+}
+
+#[test]
+fn test_error_handler_builds_response() {
+    let (handle, port) =
+        start_server(ServerBuilder::new(HttpServer(FailingService)).error_handler(ProblemJson));
+
+    let response = get(port, "/missing");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(response.contains("\r\nContent-Type: application/problem+json\r\n"));
+    assert_eq!(response.matches("Content-Type").count(), 1);
+    assert!(response.ends_with(r#"{"status":404,"instance":"/missing"}"#));
+
+    let response = get(port, "/io");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(response.ends_with(r#"{"status":500,"instance":"/io"}"#));
+
+    // a body larger than the request buffer moves it while it is read
+    let body = "a".repeat(100_000);
+    let response = send_raw(
+        port,
+        format!(
+            "POST /upload HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        ),
+    );
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(response.ends_with(r#"{"status":500,"instance":"/upload"}"#));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_http_error_values() {
+    let err = HttpError::with_message(StatusCode::CONFLICT, "already exists");
+    assert_eq!(err.status(), StatusCode::CONFLICT);
+    assert_eq!(err.message(), Some("already exists"));
+    assert_eq!(err.to_string(), "409 Conflict: already exists");
+
+    let err: io::Error = HttpError::new(StatusCode::GONE).into();
+    assert_eq!(HttpError::from_io(&err).unwrap().status(), StatusCode::GONE);
+    assert!(HttpError::from_io(&io::Error::other("plain")).is_none());
+}
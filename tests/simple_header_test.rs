@@ -6,7 +6,7 @@
 //! - Above limit (should fail with TooManyHeaders)
 
 use bytes::BufMut;
-use may_minihttp::{HttpServer, HttpService, Request, Response};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerHandle};
 use std::io::{self, Read, Write};
 use std::net::TcpStream;
 use std::sync::Once;
@@ -38,7 +38,7 @@ impl HttpService for TestService {
 }
 
 /// Start a test server and return its handle
-fn start_test_server(port: u16) -> may::coroutine::JoinHandle<()> {
+fn start_test_server(port: u16) -> ServerHandle {
     init_may_runtime();
 
     let handle = HttpServer(TestService)
@@ -110,10 +110,7 @@ fn test_3_headers_well_below_limit() {
     assert!(response.contains("Headers: 3"), "Should receive 3 headers");
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.shutdown(Duration::from_secs(1));
 }
 
 #[test]
@@ -134,10 +131,7 @@ fn test_10_headers_below_limit() {
     );
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.shutdown(Duration::from_secs(1));
 }
 
 #[test]
@@ -158,10 +152,7 @@ fn test_16_headers_at_default_limit() {
     );
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.shutdown(Duration::from_secs(1));
 }
 
 #[test]
@@ -189,10 +180,7 @@ fn test_17_headers_exceeds_default_limit() {
     }
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.shutdown(Duration::from_secs(1));
 }
 
 #[test]
@@ -221,10 +209,7 @@ fn test_20_headers_well_over_limit() {
     }
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.shutdown(Duration::from_secs(1));
 }
 
 #[test]
@@ -253,8 +238,5 @@ fn test_32_headers_far_over_limit() {
     }
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.shutdown(Duration::from_secs(1));
 }
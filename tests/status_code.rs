@@ -0,0 +1,110 @@
+//! Tests for typed status codes
+//!
+//! These tests verify that:
+//! 1. `Response::status` writes the canonical reason phrase
+//! 2. A custom reason phrase set with `status_code` is kept
+//! 3. An unregistered code gets an empty reason phrase
+//! 4. HTTP/1.0 requests get an HTTP/1.0 status line
+//! 5. Codes outside of 100 to 999 are rejected by `try_status_code`
+
+mod common;
+
+use common::{get, send_raw, start_server};
+use may_minihttp::{
+    HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle, StatusCode,
+};
+use std::io;
+use std::time::Duration;
+
+/// The path picks the status
+#[derive(Clone)]
+struct StatusService;
+
+impl HttpService for StatusService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/created" => rsp.status(StatusCode::CREATED),
+            "/teapot" => rsp.status_code(418, "I'm a teapot"),
+            "/custom" => rsp.status_code(404, "Nothing Here"),
+            "/unregistered" => rsp.status(StatusCode::from_u16(599).unwrap()),
+            "/checked" => rsp.try_status_code(299, "Checked")?,
+            "/invalid" => rsp.try_status_code(1000, "Too Big")?,
+            _ => rsp,
+        };
+        rsp.body("done");
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(StatusService)))
+}
+
+#[test]
+fn test_canonical_reason_phrase() {
+    let (handle, port) = start_test_server();
+
+    assert!(get(port, "/").starts_with("HTTP/1.1 200 OK\r\nServer: M\r\n"));
+    assert!(get(port, "/created").starts_with("HTTP/1.1 201 Created\r\nServer: M\r\n"));
+    assert!(get(port, "/teapot").starts_with("HTTP/1.1 418 I'm a teapot\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_custom_and_missing_reason_phrase() {
+    let (handle, port) = start_test_server();
+
+    let response = get(port, "/custom");
+    assert!(response.starts_with("HTTP/1.1 404 Nothing Here\r\n"));
+    assert!(response.ends_with("\r\n\r\ndone"));
+    assert!(get(port, "/unregistered").starts_with("HTTP/1.1 599 \r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_checked_status_code() {
+    let (handle, port) = start_test_server();
+
+    assert!(get(port, "/checked").starts_with("HTTP/1.1 299 Checked\r\n"));
+    // the service fails with the error, nothing it set is sent
+    let response = get(port, "/invalid");
+    assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(!response.contains("Too Big"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_http10_status_line() {
+    let (handle, port) = start_test_server();
+
+    let response = send_raw(port, "GET /created HTTP/1.0\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.0 201 Created\r\n"));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_status_code_values() {
+    assert_eq!(StatusCode::default(), StatusCode::OK);
+    assert_eq!(StatusCode::try_from(204), Ok(StatusCode::NO_CONTENT));
+    assert_eq!(u16::from(StatusCode::NOT_MODIFIED), 304);
+    assert_eq!(StatusCode::BAD_GATEWAY.to_string(), "502 Bad Gateway");
+    assert_eq!(StatusCode::from_u16(799).unwrap().to_string(), "799");
+
+    assert!(StatusCode::CONTINUE.is_informational());
+    assert!(StatusCode::NO_CONTENT.is_success());
+    assert!(StatusCode::SEE_OTHER.is_redirection());
+    assert!(StatusCode::TOO_MANY_REQUESTS.is_client_error());
+    assert!(StatusCode::SERVICE_UNAVAILABLE.is_server_error());
+    assert!(!StatusCode::OK.is_client_error());
+
+    assert_eq!(StatusCode::from_u16(99).unwrap_err().code(), 99);
+    let err = StatusCode::from_u16(1000).unwrap_err();
+    assert_eq!(err.to_string(), "invalid status code 1000");
+    let err: io::Error = err.into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -1,6 +1,6 @@
 //! Test server helpers with configurable MaxHeaders support
 
-use may_minihttp::{HttpServer, HttpService, MaxHeaders, Request, Response};
+use may_minihttp::{HttpServer, HttpService, MaxHeaders, Request, Response, ServerBuilder};
 use std::io;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -31,10 +31,10 @@ impl ConfigurableTestServer {
         let shutdown = Arc::new(AtomicBool::new(false));
         let shutdown_clone = Arc::clone(&shutdown);
 
-        // For now, start with default - we'll add configuration support next
         let server_handle = thread::spawn(move || {
-            let _server = HttpServer(TestService)
-                .start(&format!("127.0.0.1:{}", port))
+            let _server = ServerBuilder::new(HttpServer(TestService))
+                .max_headers(max_headers)
+                .bind(format!("127.0.0.1:{}", port))
                 .expect("Failed to start test server");
 
             while !shutdown_clone.load(Ordering::Relaxed) {
@@ -0,0 +1,140 @@
+//! Tests for the connection timeouts of `HttpConfig`
+//!
+//! These tests verify that:
+//! 1. Idle keep-alive connections are closed after the keep-alive timeout
+//! 2. A request head that trickles in too slowly gets `408 Request Timeout`
+//! 3. A stalled request body gets `408 Request Timeout`
+//! 4. Each case is reported by `ServerHandle::stats`
+
+mod common;
+
+use common::{connect, read_to_close, start_server};
+use may_minihttp::{
+    HttpConfig, HttpServer, HttpService, Request, Response, ServerBuilder, ServerHandle,
+};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Service that echoes the request body
+#[derive(Clone)]
+struct EchoService;
+
+impl HttpService for EchoService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        res.body_vec(body);
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server(config: HttpConfig) -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(EchoService)).config(config))
+}
+
+#[test]
+fn test_keep_alive_timeout_closes_idle_connection() {
+    let config = HttpConfig::new().with_keep_alive_timeout(Duration::from_millis(200));
+    let (handle, port) = start_test_server(config);
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi")
+        .unwrap();
+    let start = Instant::now();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.ends_with("hi"));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    let stats = handle.stats();
+    assert_eq!(stats.keep_alive_timeouts, 1);
+    assert_eq!(stats.header_read_timeouts, 0);
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_keep_alive_without_timeout_stays_open() {
+    let (handle, port) = start_test_server(HttpConfig::new());
+
+    let mut stream = connect(port);
+    stream
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200 OK"));
+
+    // still open, the read times out on the client side
+    let err = stream.read(&mut buf).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ));
+    assert_eq!(handle.stats().keep_alive_timeouts, 0);
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_slow_request_head_gets_408() {
+    let config = HttpConfig::new().with_header_read_timeout(Duration::from_millis(300));
+    let (handle, port) = start_test_server(config);
+
+    let mut stream = connect(port);
+    stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    std::thread::sleep(Duration::from_millis(150));
+    // still trickling in, but the deadline counts from the first bytes
+    stream.write_all(b"Host: localhost\r\n").unwrap();
+
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout"));
+    assert!(response.contains("Connection: close"));
+
+    let stats = handle.stats();
+    assert_eq!(stats.header_read_timeouts, 1);
+    assert_eq!(stats.keep_alive_timeouts, 0);
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_stalled_body_gets_408() {
+    let config = HttpConfig::new().with_body_read_timeout(Duration::from_millis(200));
+    let (handle, port) = start_test_server(config);
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc")
+        .unwrap();
+
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout"));
+    assert!(response.contains("Connection: close"));
+
+    assert_eq!(handle.stats().body_read_timeouts, 1);
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_body_timeout_does_not_limit_idle_wait() {
+    let config = HttpConfig::new().with_body_read_timeout(Duration::from_millis(100));
+    let (handle, port) = start_test_server(config);
+
+    let mut stream = connect(port);
+    stream.write_all(b"GET /a HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200 OK"));
+
+    // idle for longer than the body timeout, the connection is kept
+    std::thread::sleep(Duration::from_millis(300));
+    stream.write_all(b"GET /b HTTP/1.1\r\n\r\n").unwrap();
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200 OK"));
+
+    let stats = handle.stats();
+    assert_eq!(stats.connections_accepted, 1);
+    assert_eq!(stats.body_read_timeouts, 0);
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
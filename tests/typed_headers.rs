@@ -0,0 +1,296 @@
+//! Tests for the header accessors of `Request`
+//!
+//! These tests verify that:
+//! 1. `header`, `header_all` and `header_list` ignore the case of the name
+//! 2. The typed accessors parse `Host`, `Content-Type`, `Accept`,
+//!    `Authorization`, `Connection`, `If-None-Match`, `Range` and `Cookie`
+//! 3. Malformed values are reported as `HeaderError`s, not panics
+
+mod common;
+
+use common::{connect, start_server};
+use may_minihttp::{
+    ByteRange, EntityTag, HttpServer, HttpService, IfNoneMatch, Request, Response, ServerBuilder,
+    ServerHandle,
+};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// Answers with one line per accessor, the path picks the accessor
+#[derive(Clone)]
+struct HeaderService;
+
+impl HttpService for HeaderService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let text = |v: &[u8]| String::from_utf8_lossy(v).into_owned();
+        let body = match req.path() {
+            "/raw" => format!(
+                "first={:?}\nall={:?}\nlist={:?}\nmissing={:?}",
+                req.header("x-multi").map(text),
+                req.header_all("X-MULTI").map(text).collect::<Vec<_>>(),
+                req.header_list("x-multi").map(text).collect::<Vec<_>>(),
+                req.header("x-none"),
+            ),
+            "/host" => match req.host() {
+                Ok(Some(host)) => format!("{} {:?}", host.host(), host.port()),
+                other => format!("{other:?}"),
+            },
+            "/content-type" => match req.content_type() {
+                Ok(Some(ct)) => format!(
+                    "{} {} {} json={} {:?}",
+                    ct.essence(),
+                    ct.main_type(),
+                    ct.subtype(),
+                    ct.is("Application/JSON"),
+                    ct.params().collect::<Vec<_>>()
+                ),
+                other => format!("{other:?}"),
+            },
+            "/accept" => match req.accept() {
+                Ok(ranges) => ranges
+                    .iter()
+                    .map(|r| format!("{}:{}", r.essence(), r.quality()))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Err(e) => e.to_string(),
+            },
+            "/auth" => match req.authorization() {
+                Ok(Some(auth)) => format!(
+                    "{} {} {:?}",
+                    auth.scheme(),
+                    auth.credentials(),
+                    auth.bearer()
+                ),
+                other => format!("{other:?}"),
+            },
+            "/connection" => {
+                let c = req.connection();
+                format!("{} {} {}", c.is_close(), c.is_keep_alive(), c.is_upgrade())
+            }
+            "/etag" => match req.if_none_match() {
+                Ok(Some(inm)) => format!(
+                    "{} {}",
+                    inm.matches(&EntityTag::strong("v1")),
+                    inm.matches(&EntityTag::strong("v9"))
+                ),
+                other => format!("{other:?}"),
+            },
+            "/range" => format!("{:?}", req.range()),
+            "/cookie" => format!(
+                "{:?} {:?}",
+                req.cookies().collect::<Vec<_>>(),
+                req.cookie("b")
+            ),
+            _ => String::new(),
+        };
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+/// Start a test server on an OS assigned port
+fn start_test_server() -> (ServerHandle, u16) {
+    start_server(ServerBuilder::new(HttpServer(HeaderService)))
+}
+
+/// Send a request for `path` with the given header lines, returns the body
+fn get(port: u16, path: &str, headers: &str) -> String {
+    let mut stream = connect(port);
+    let request = format!("GET {path} HTTP/1.1\r\n{headers}Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 1024];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => response.extend_from_slice(&buffer[..n]),
+        }
+        let text = String::from_utf8_lossy(&response);
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .find_map(|l| l.strip_prefix("Content-Length: "))
+                .and_then(|l| l.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if body.len() >= length {
+                return body.to_owned();
+            }
+        }
+    }
+    panic!("incomplete response to {path}");
+}
+
+#[test]
+fn test_raw_header_lookup() {
+    let (handle, port) = start_test_server();
+
+    let body = get(
+        port,
+        "/raw",
+        "X-Multi: a, \"b,c\"\r\nx-multi: ,d\r\nOther: x\r\n",
+    );
+    assert_eq!(
+        body,
+        "first=Some(\"a, \\\"b,c\\\"\")\nall=[\"a, \\\"b,c\\\"\", \",d\"]\n\
+         list=[\"a\", \"\\\"b,c\\\"\", \"d\"]\nmissing=None"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_host() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(port, "/host", "Host: example.com\r\n"),
+        "example.com None"
+    );
+    assert_eq!(
+        get(port, "/host", "Host: example.com:8080\r\n"),
+        "example.com Some(8080)"
+    );
+    assert_eq!(get(port, "/host", "Host: [::1]:443\r\n"), "[::1] Some(443)");
+    assert_eq!(get(port, "/host", ""), "Ok(None)");
+    assert!(get(port, "/host", "Host: a:99999\r\n").starts_with("Err("));
+    assert!(get(port, "/host", "Host: a\r\nHost: b\r\n").starts_with("Err("));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_content_type() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(
+            port,
+            "/content-type",
+            "Content-Type: application/json; charset=UTF-8; note=\"a;\\\"b\"\r\n"
+        ),
+        "application/json application json json=true \
+         [(\"charset\", \"UTF-8\"), (\"note\", \"a;\\\"b\")]"
+    );
+    assert!(get(port, "/content-type", "Content-Type: text\r\n").starts_with("Err("));
+    assert!(get(
+        port,
+        "/content-type",
+        "Content-Type: text/html; charset\r\n"
+    )
+    .starts_with("Err("));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_accept_is_sorted_by_quality() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(
+            port,
+            "/accept",
+            "Accept: text/html;q=0.5, application/json\r\nAccept: */*;q=0.1, text/plain;q=0.75\r\n"
+        ),
+        "application/json:1000 text/plain:750 text/html:500 */*:100"
+    );
+    assert_eq!(get(port, "/accept", ""), "");
+    assert_eq!(
+        get(port, "/accept", "Accept: text/html, nonsense\r\n"),
+        "invalid Accept header"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_authorization() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(port, "/auth", "Authorization: Bearer abc.def\r\n"),
+        "Bearer abc.def Some(\"abc.def\")"
+    );
+    assert_eq!(
+        get(port, "/auth", "Authorization: Basic dXNlcjpwYXNz\r\n"),
+        "Basic dXNlcjpwYXNz None"
+    );
+    assert!(get(port, "/auth", "Authorization: B@d x\r\n").starts_with("Err("));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_connection_options() {
+    let (handle, port) = start_test_server();
+
+    // the helper always appends `Connection: close`
+    assert_eq!(get(port, "/connection", ""), "true false false");
+    assert_eq!(
+        get(port, "/connection", "Connection: Keep-Alive, Upgrade\r\n"),
+        "true true true"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_if_none_match() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(port, "/etag", "If-None-Match: \"v0\", W/\"v1\"\r\n"),
+        "true false"
+    );
+    assert_eq!(get(port, "/etag", "If-None-Match: *\r\n"), "true true");
+    assert!(get(port, "/etag", "If-None-Match: v1\r\n").starts_with("Err("));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_range() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(port, "/range", "Range: bytes=0-99, 200-, -50\r\n"),
+        "Ok(Some([FromTo(0, 99), From(200), Last(50)]))"
+    );
+    assert_eq!(get(port, "/range", "Range: items=1-2\r\n"), "Ok(None)");
+    assert!(get(port, "/range", "Range: bytes=9-1\r\n").starts_with("Err("));
+    assert!(get(port, "/range", "Range: bytes=\r\n").starts_with("Err("));
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_cookies() {
+    let (handle, port) = start_test_server();
+
+    assert_eq!(
+        get(port, "/cookie", "Cookie: a=1; b=2;junk\r\nCookie: c=\r\n"),
+        "[(\"a\", \"1\"), (\"b\", \"2\"), (\"c\", \"\")] Some(\"2\")"
+    );
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_header_values() {
+    assert_eq!(ByteRange::FromTo(0, 99).resolve(50), Some(0..50));
+    assert_eq!(ByteRange::From(10).resolve(50), Some(10..50));
+    assert_eq!(ByteRange::Last(80).resolve(50), Some(0..50));
+    assert_eq!(ByteRange::From(50).resolve(50), None);
+
+    let weak = EntityTag::parse("W/\"abc\"").unwrap();
+    assert!(weak.is_weak());
+    assert_eq!(weak.tag(), "abc");
+    assert!(weak.weak_eq(&EntityTag::strong("abc")));
+    assert!(!weak.strong_eq(&EntityTag::strong("abc")));
+    assert_eq!(weak.to_string(), "W/\"abc\"");
+    assert!(EntityTag::parse("abc").is_none());
+
+    let tags = IfNoneMatch::Tags(vec![EntityTag::strong("x")]);
+    assert!(tags.matches(&EntityTag::weak("x")));
+}
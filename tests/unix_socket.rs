@@ -0,0 +1,199 @@
+//! Tests for serving over a Unix domain socket
+//!
+//! These tests verify that:
+//! 1. Requests are served over a socket file, without a peer address
+//! 2. The socket file gets the configured permissions and is removed on shutdown
+//! 3. A stale socket file is replaced, a live one is not
+//! 4. Abstract sockets work on Linux
+//! 5. Forwarding headers are only followed with `trust_unix_peers`
+#![cfg(unix)]
+
+mod common;
+
+use common::{init_may_runtime, read_to_close};
+use may_minihttp::{
+    ConnectionInfo, HttpService, HttpServiceFactory, Request, Response, ServerBuilder,
+    ServerHandle, UnixSocket,
+};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Answers with the peer address of the connection, if any, `/client` with
+/// the client address and scheme
+struct PeerService;
+
+impl HttpService for PeerService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.path() == "/client" {
+            let body = format!("client={:?} scheme={}", req.client_addr(), req.scheme());
+            rsp.body_vec(body.into_bytes());
+            return Ok(());
+        }
+        let info = req.connection_info().expect("served request");
+        let body = match info.peer_addr() {
+            Some(peer) => format!("peer={peer}"),
+            None => "peer=none".to_owned(),
+        };
+        rsp.body_vec(body.into_bytes());
+        Ok(())
+    }
+}
+
+struct PeerFactory;
+
+impl HttpServiceFactory for PeerFactory {
+    type Service = PeerService;
+
+    fn new_service(&self, _conn: &ConnectionInfo) -> PeerService {
+        PeerService
+    }
+}
+
+/// A socket path no other test uses
+fn socket_path(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("may_minihttp-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Files next to `path` named after it, other than `path` itself
+fn leftovers(path: &Path) -> usize {
+    let name = path.file_name().unwrap().to_str().unwrap();
+    std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .filter(|entry| {
+            let entry = entry.as_ref().unwrap().file_name();
+            let entry = entry.to_string_lossy();
+            entry != name && entry.contains(name)
+        })
+        .count()
+}
+
+fn start(socket: UnixSocket) -> io::Result<ServerHandle> {
+    init_may_runtime();
+    ServerBuilder::new(PeerFactory).bind_unix(socket)
+}
+
+/// Send a request and return the response body
+fn request(stream: UnixStream) -> String {
+    send(stream, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+}
+
+/// Send `request` and return the response body
+fn send(mut stream: UnixStream, request: &str) -> String {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    response.rsplit("\r\n\r\n").next().unwrap().to_owned()
+}
+
+#[test]
+fn test_serve_over_socket_file() {
+    let path = socket_path("serve");
+    let handle = start(UnixSocket::path(&path).mode(0o600)).expect("Failed to start server");
+    assert_eq!(
+        handle.unix_socket().and_then(|s| s.as_path()),
+        Some(path.as_path())
+    );
+
+    let meta = std::fs::metadata(&path).unwrap();
+    assert!(meta.file_type().is_socket());
+    assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+
+    assert_eq!(leftovers(&path), 0);
+
+    let body = request(UnixStream::connect(&path).expect("Failed to connect"));
+    assert_eq!(body, "peer=none");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_stale_socket_is_replaced() {
+    let path = socket_path("stale");
+    // a socket file nobody listens on any more
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let err = start(UnixSocket::path(&path).remove_stale(false)).err();
+    assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+
+    let handle = start(UnixSocket::path(&path)).expect("Failed to start server");
+    assert_eq!(request(UnixStream::connect(&path).unwrap()), "peer=none");
+
+    // the socket of a live server is kept
+    let err = start(UnixSocket::path(&path)).err();
+    assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+    assert_eq!(request(UnixStream::connect(&path).unwrap()), "peer=none");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_regular_file_is_kept() {
+    let path = socket_path("regular");
+    std::fs::write(&path, b"not a socket").unwrap();
+
+    let err = start(UnixSocket::path(&path)).err();
+    assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+    assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+
+    // nor replaced by a socket bound elsewhere to set its mode first
+    let err = start(UnixSocket::path(&path).mode(0o600)).err();
+    assert_eq!(err.map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+    assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+    assert_eq!(leftovers(&path), 0);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_abstract_socket() {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let name = format!("may_minihttp-{}-abstract", std::process::id());
+    let handle = start(UnixSocket::abstract_name(name.clone())).expect("Failed to start server");
+    assert_eq!(handle.unix_socket().and_then(|s| s.as_path()), None);
+
+    let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+    let body = request(UnixStream::connect_addr(&addr).expect("Failed to connect"));
+    assert_eq!(body, "peer=none");
+
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_forwarding_headers_of_unix_peers() {
+    let path = socket_path("forwarded");
+    let request = "GET /client HTTP/1.1\r\nX-Forwarded-For: 203.0.113.7\r\n\
+                   X-Forwarded-Proto: https\r\nConnection: close\r\n\r\n";
+
+    // anyone who can connect to the socket could send them
+    let handle = start(UnixSocket::path(&path)).expect("Failed to start server");
+    let body = send(UnixStream::connect(&path).unwrap(), request);
+    assert_eq!(body, "client=None scheme=http");
+    assert!(handle.shutdown(Duration::from_secs(1)));
+
+    let handle = ServerBuilder::new(PeerFactory)
+        .trust_unix_peers(true)
+        .bind_unix(UnixSocket::path(&path))
+        .expect("Failed to start server");
+    let body = send(UnixStream::connect(&path).unwrap(), request);
+    assert_eq!(body, "client=Some(203.0.113.7) scheme=https");
+    // a proxy that does not name the client
+    let body = send(
+        UnixStream::connect(&path).unwrap(),
+        "GET /client HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(body, "client=None scheme=http");
+    assert!(handle.shutdown(Duration::from_secs(1)));
+}